@@ -3,6 +3,7 @@ precision mediump float;
 
 #define NUM_DIR_LIGHTS 0
 #define NUM_POINT_LIGHTS 0
+#define NUM_TUBE_LIGHTS 0
 
 // Typedefs
 struct Light {
@@ -12,6 +13,13 @@ struct Light {
     float attenuation;
 };
 
+// Tube lights: light.position_or_direction is the start of the segment
+struct TubeLight {
+    Light light;
+    vec3 end;
+    float radius;
+};
+
 // User-defined uniforms
 uniform sampler2D u_tex_diffuse;
 uniform sampler2D u_tex_normal;
@@ -29,6 +37,10 @@ uniform Light u_dir_lights[NUM_DIR_LIGHTS];
 uniform Light u_point_lights[NUM_POINT_LIGHTS];
 #endif
 
+#if NUM_TUBE_LIGHTS > 0
+uniform TubeLight u_tube_lights[NUM_TUBE_LIGHTS];
+#endif
+
 uniform vec4 u_ambiant_light;
 
 // Varyings
@@ -37,6 +49,10 @@ varying vec3 v_normal;
 varying vec3 v_position;
 varying mat3 v_tbn_matrix;
 
+#ifdef USE_AO
+varying float v_ao;
+#endif
+
 float lambertDiffuse(
   vec3 lightDirection,
   vec3 surfaceNormal) {
@@ -60,6 +76,26 @@ float beckmannSpecular(
   return beckmannDistribution(dot(surfaceNormal, normalize(lightDirection + viewDirection)), roughness);
 }
 
+// Representative point approximation for tube (capsule) lights.
+// Returns the point of the light's segment [tube_start, tube_end], widened by
+// tube_radius, that is closest to the reflection ray, to be lit like a point light
+// for the specular term. The diffuse term can use the closest point to the fragment.
+vec3 tube_light_point(vec3 tube_start, vec3 tube_end, float tube_radius, vec3 position, vec3 reflection) {
+    vec3 start = tube_start - position;
+    vec3 segment = tube_end - tube_start;
+    float reflection_dot_segment = dot(reflection, segment);
+    float denominator = dot(segment, segment) - reflection_dot_segment * reflection_dot_segment;
+    float t = clamp(
+        (dot(reflection, start) * reflection_dot_segment - dot(start, segment)) / max(denominator, 0.0001),
+        0.0,
+        1.0
+    );
+    vec3 closest = start + segment * t;
+    vec3 center_to_ray = dot(closest, reflection) * reflection - closest;
+    closest += center_to_ray * clamp(tube_radius / max(length(center_to_ray), 0.0001), 0.0, 1.0);
+    return position + closest;
+}
+
 vec4 light_value(vec3 light_direction, vec3 light_color, float light_intensity, vec3 normal, vec3 view_direction) {
     vec3 light_dir = normalize(-light_direction);
     float power = lambertDiffuse(light_dir,normal) + beckmannSpecular(light_dir,view_direction,normal,u_roughness);
@@ -69,7 +105,13 @@ vec4 light_value(vec3 light_direction, vec3 light_color, float light_intensity,
 vec3 get_normal(){
     vec3 normal = texture2D(u_tex_normal,vec2(v_tex_coordinates.x, 1.0 - v_tex_coordinates.y)).rgb;
     normal = normalize(normal * 2.0 - 1.0);
-    return normalize(v_tbn_matrix * normal);
+    normal = normalize(v_tbn_matrix * normal);
+#ifdef FLIP_BACKFACE_NORMALS
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+#endif
+    return normal;
 }
 
 void main() {
@@ -77,6 +119,9 @@ void main() {
     vec3 view_direction = normalize(u_camera_position - v_position);
     vec4 diffuse = texture2D(u_tex_diffuse, vec2(v_tex_coordinates.x, 1.0 - v_tex_coordinates.y));
     vec3 computed_light_color = u_ambiant_light.rgb*u_ambiant_light.a;
+#ifdef USE_AO
+    computed_light_color *= v_ao;
+#endif
     float total_intensity = u_ambiant_light.a;
 #if NUM_DIR_LIGHTS > 0
     for(int i = 0; i < NUM_DIR_LIGHTS; i++){
@@ -90,6 +135,14 @@ void main() {
         vec4 point_light = light_value(direction, u_point_lights[i].color, u_point_lights[i].intensity,normal,view_direction);
         computed_light_color += point_light.rgb*point_light.a;
     }
+#endif
+#if NUM_TUBE_LIGHTS > 0
+    vec3 reflection = reflect(-view_direction, normal);
+    for(int i = 0; i < NUM_TUBE_LIGHTS; i++){
+        vec3 light_point = tube_light_point(u_tube_lights[i].light.position_or_direction, u_tube_lights[i].end, u_tube_lights[i].radius, v_position, reflection);
+        vec4 tube_light = light_value(v_position - light_point, u_tube_lights[i].light.color, u_tube_lights[i].light.intensity,normal,view_direction);
+        computed_light_color += tube_light.rgb*tube_light.a;
+    }
 #endif
     gl_FragColor = vec4(diffuse.rgb*computed_light_color,diffuse.a);
 }
\ No newline at end of file
@@ -18,6 +18,11 @@ varying vec3 v_position;
 varying mat3 v_tbn_matrix;
 #endif
 
+#ifdef USE_AO
+attribute float a_ao;
+varying float v_ao;
+#endif
+
 mat3 compute_tbn_matrix(){
     vec3 t = normalize(vec3(u_world_transform * vec4(a_tangent, 0.0)));
     vec3 b = normalize(vec3(u_world_transform * vec4( cross(a_normal,a_tangent), 0.0)));
@@ -34,4 +39,7 @@ void main() {
     vec4 normal = u_world_transform * vec4(a_normal,1.0);
     v_normal = normal.xyz/normal.w;
     v_tbn_matrix = compute_tbn_matrix();
+#ifdef USE_AO
+    v_ao = a_ao;
+#endif
 }
@@ -2,6 +2,7 @@ precision mediump float;
 
 #define NUM_DIR_LIGHTS 0
 #define NUM_POINT_LIGHTS 0
+#define NUM_TUBE_LIGHTS 0
 
 // Typedefs
 struct Light {
@@ -11,6 +12,13 @@ struct Light {
     float attenuation;
 };
 
+// Tube lights: light.position_or_direction is the start of the segment
+struct TubeLight {
+    Light light;
+    vec3 end;
+    float radius;
+};
+
 // User-defined uniforms
 uniform sampler2D u_tex_diffuse;
 uniform sampler2D u_tex_normal;
@@ -28,6 +36,10 @@ uniform Light u_dir_lights[NUM_DIR_LIGHTS];
 uniform Light u_point_lights[NUM_POINT_LIGHTS];
 #endif
 
+#if NUM_TUBE_LIGHTS > 0
+uniform TubeLight u_tube_lights[NUM_TUBE_LIGHTS];
+#endif
+
 uniform vec4 u_ambiant_light;
 
 // Varyings
@@ -36,8 +48,13 @@ varying vec3 v_normal;
 varying vec3 v_position;
 varying mat3 v_tbn_matrix;
 
+#ifdef USE_AO
+varying float v_ao;
+#endif
+
 #pragma glslify: lambert = require(glsl-diffuse-lambert) 
 #pragma glslify: beckmann = require(glsl-specular-beckmann) 
+#pragma glslify: tube_light_point = require(./tube_light.glsl)
 
 
 vec4 light_value(vec3 light_direction, vec3 light_color, float light_intensity, vec3 normal, vec3 view_direction) {
@@ -49,7 +66,13 @@ vec4 light_value(vec3 light_direction, vec3 light_color, float light_intensity,
 vec3 get_normal(){
     vec3 normal = texture2D(u_tex_normal,vec2(v_tex_coordinates.x, 1.0 - v_tex_coordinates.y)).rgb;
     normal = normalize(normal * 2.0 - 1.0);
-    return normalize(v_tbn_matrix * normal);
+    normal = normalize(v_tbn_matrix * normal);
+#ifdef FLIP_BACKFACE_NORMALS
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+#endif
+    return normal;
 }
 
 void main() {
@@ -57,6 +80,9 @@ void main() {
     vec3 view_direction = normalize(u_camera_position - v_position);
     vec4 diffuse = texture2D(u_tex_diffuse, vec2(v_tex_coordinates.x, 1.0 - v_tex_coordinates.y));
     vec3 computed_light_color = u_ambiant_light.rgb*u_ambiant_light.a;
+#ifdef USE_AO
+    computed_light_color *= v_ao;
+#endif
     float total_intensity = u_ambiant_light.a;
 #if NUM_DIR_LIGHTS > 0
     for(int i = 0; i < NUM_DIR_LIGHTS; i++){
@@ -70,6 +96,14 @@ void main() {
         vec4 point_light = light_value(direction, u_point_lights[i].color, u_point_lights[i].intensity,normal,view_direction);
         computed_light_color += point_light.rgb*point_light.a;
     }
+#endif
+#if NUM_TUBE_LIGHTS > 0
+    vec3 reflection = reflect(-view_direction, normal);
+    for(int i = 0; i < NUM_TUBE_LIGHTS; i++){
+        vec3 light_point = tube_light_point(u_tube_lights[i].light.position_or_direction, u_tube_lights[i].end, u_tube_lights[i].radius, v_position, reflection);
+        vec4 tube_light = light_value(v_position - light_point, u_tube_lights[i].light.color, u_tube_lights[i].light.intensity,normal,view_direction);
+        computed_light_color += tube_light.rgb*tube_light.a;
+    }
 #endif
     gl_FragColor = vec4(diffuse.rgb*computed_light_color,diffuse.a);
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+// Fades transparent fragments out as they get close to the camera.
+// u_near_fade.x is the distance at which fading starts,
+// u_near_fade.y the distance at which fragments become fully transparent.
+uniform vec2 u_near_fade;
+
+float near_fade(float camera_distance) {
+    return clamp((camera_distance - u_near_fade.y) / (u_near_fade.x - u_near_fade.y), 0.0, 1.0);
+}
+
+#pragma glslify: export(near_fade)
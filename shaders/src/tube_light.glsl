@@ -0,0 +1,21 @@
+// Representative point approximation for tube (capsule) lights.
+// Returns the point of the light's segment [tube_start, tube_end], widened by
+// tube_radius, that is closest to the reflection ray, to be lit like a point light
+// for the specular term. The diffuse term can use the closest point to the fragment.
+vec3 tube_light_point(vec3 tube_start, vec3 tube_end, float tube_radius, vec3 position, vec3 reflection) {
+    vec3 start = tube_start - position;
+    vec3 segment = tube_end - tube_start;
+    float reflection_dot_segment = dot(reflection, segment);
+    float denominator = dot(segment, segment) - reflection_dot_segment * reflection_dot_segment;
+    float t = clamp(
+        (dot(reflection, start) * reflection_dot_segment - dot(start, segment)) / max(denominator, 0.0001),
+        0.0,
+        1.0
+    );
+    vec3 closest = start + segment * t;
+    vec3 center_to_ray = dot(closest, reflection) * reflection - closest;
+    closest += center_to_ray * clamp(tube_radius / max(length(center_to_ray), 0.0001), 0.0, 1.0);
+    return position + closest;
+}
+
+#pragma glslify: export(tube_light_point)
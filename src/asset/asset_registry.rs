@@ -1,10 +1,14 @@
 //! Asset registry module
 
+use crate::error::Error;
 use crate::renderer::MeshData;
-use crate::renderer::{Material, MaterialInstance};
+use crate::renderer::{Material, MaterialInstance, SamplerPolicy};
+use crate::utils::PixelLayout;
+use js_sys::Float32Array;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use wasm_bindgen::JsValue;
 use web_sys::{HtmlImageElement, WebGlRenderingContext, WebGlTexture};
 
 #[non_exhaustive]
@@ -26,6 +30,12 @@ pub struct AssetRegistry {
 
     /// Index linking each initial String ID to an internal usize ID.
     index: HashMap<String, usize>,
+
+    /// Number of texture units a material and its instances may use together.
+    max_texture_units: u32,
+
+    /// Number of texture units, at the top of the range, kept for engine-internal textures.
+    reserved_texture_units: u32,
 }
 
 impl AssetRegistry {
@@ -34,15 +44,45 @@ impl AssetRegistry {
         AssetRegistry {
             assets: Vec::new(),
             index: HashMap::new(),
+            max_texture_units: crate::utils::constants::DEFAULT_MAX_TEXTURE_UNITS,
+            reserved_texture_units: 0,
         }
     }
 
+    /// Sets the number of texture units a material and its instances may use together.  
+    /// Materials needing more are rejected at registration time.
+    pub fn set_max_texture_units(&mut self, max_texture_units: u32) {
+        self.max_texture_units = max_texture_units;
+    }
+
+    /// Getter for max_texture_units
+    pub fn get_max_texture_units(&self) -> u32 {
+        self.max_texture_units
+    }
+
+    /// Keeps the last `reserved_texture_units` texture units for engine-internal textures,
+    /// so that material textures never get bound to them.
+    pub fn set_reserved_texture_units(&mut self, reserved_texture_units: u32) {
+        self.reserved_texture_units = reserved_texture_units;
+    }
+
+    /// Getter for reserved_texture_units
+    pub fn get_reserved_texture_units(&self) -> u32 {
+        self.reserved_texture_units
+    }
+
+    /// Number of texture units left for material textures, below the reserved ones.
+    pub fn get_available_texture_units(&self) -> u32 {
+        self.max_texture_units
+            .saturating_sub(self.reserved_texture_units)
+    }
+
     /// Register mesh data from the byte array from a `MeshFile`
     pub fn register_mesh_data(
         &mut self,
         context: &WebGlRenderingContext,
         wmesh_data: &[u8],
-    ) -> Result<String, String> {
+    ) -> Result<String, Error> {
         let mesh_data_result = super::deserialize_wmesh(context, wmesh_data);
         if let Ok(mesh_data) = mesh_data_result {
             let id = mesh_data.get_id().to_owned();
@@ -51,12 +91,12 @@ impl AssetRegistry {
                 .push(Asset::MeshData(Rc::new(RefCell::new(mesh_data))));
             Ok(id)
         } else {
-            Err(String::from("Could not parse the mesh file!"))
+            Err(Error::Load(String::from("Could not parse the mesh file!")))
         }
     }
 
     /// Register a material from the byte array of a `MaterialFile`
-    pub fn register_material(&mut self, wmaterial_data: &[u8]) -> Result<String, String> {
+    pub fn register_material(&mut self, wmaterial_data: &[u8]) -> Result<String, Error> {
         let mat_data_result = super::deserialize_wmaterial(&self, wmaterial_data);
         match mat_data_result {
             Ok(material) => {
@@ -71,7 +111,7 @@ impl AssetRegistry {
     }
 
     /// Register a material isntance from the byte array of a `MaterialInstanceFile`
-    pub fn register_material_instance(&mut self, wmaterial_data: &[u8]) -> Result<String, String> {
+    pub fn register_material_instance(&mut self, wmaterial_data: &[u8]) -> Result<String, Error> {
         let mat_data_result = super::deserialize_wmatinstance(&self, wmaterial_data);
         match mat_data_result {
             Ok(matinstance) => {
@@ -85,28 +125,121 @@ impl AssetRegistry {
         }
     }
 
-    /// Register a new texture from an Image reference
+    /// Register a new texture from an Image reference, sampled according to `sampler`
     pub fn register_texture(
         &mut self,
         context: &WebGlRenderingContext,
         image: &HtmlImageElement,
         id: String,
-    ) -> Result<String, String> {
-        match context.create_texture() {
-            None => Err(String::from("Could not create texture")),
-            Some(texture) => {
-                context.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
-                let res = context.tex_image_2d_with_u32_and_u32_and_image(
+        sampler: &SamplerPolicy,
+    ) -> Result<String, Error> {
+        self.register_texture_with(context, id, sampler, |context| {
+            context.tex_image_2d_with_u32_and_u32_and_image(
+                WebGlRenderingContext::TEXTURE_2D,
+                0,
+                WebGlRenderingContext::RGBA as i32,
+                WebGlRenderingContext::RGBA,
+                WebGlRenderingContext::UNSIGNED_BYTE,
+                image,
+            )
+        })
+    }
+
+    /// Register a new texture from raw 8-bit pixel data, sampled according to `sampler`.  
+    /// `data` must contain exactly the number of values needed by `layout`.  
+    /// Non-power-of-two textures are clamped to the edge. See `SamplerPolicy::for_size`.
+    pub fn register_texture_from_pixels(
+        &mut self,
+        context: &WebGlRenderingContext,
+        data: &[u8],
+        layout: PixelLayout,
+        id: String,
+        sampler: &SamplerPolicy,
+    ) -> Result<String, Error> {
+        layout.check_data_length(data.len())?;
+        let sampler = sampler.for_size(layout.width, layout.height);
+        self.register_texture_with(context, id, &sampler, |context| {
+            context.pixel_storei(WebGlRenderingContext::UNPACK_ALIGNMENT, 1);
+            let result = context
+                .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
                     WebGlRenderingContext::TEXTURE_2D,
                     0,
-                    WebGlRenderingContext::RGBA as i32,
-                    WebGlRenderingContext::RGBA,
+                    layout.format.get_gl_format() as i32,
+                    layout.width as i32,
+                    layout.height as i32,
+                    0,
+                    layout.format.get_gl_format(),
                     WebGlRenderingContext::UNSIGNED_BYTE,
-                    image,
+                    Some(data),
                 );
-                match res {
-                    Err(_) => Err(String::from("Texture binding failed.")),
+            context.pixel_storei(WebGlRenderingContext::UNPACK_ALIGNMENT, 4);
+            result
+        })
+    }
+
+    /// Register a new texture from raw 32-bit float pixel data, sampled according to `sampler`.  
+    /// Requires the `OES_texture_float` extension. Float textures are never mipmapped, and
+    /// use nearest filtering unless `OES_texture_float_linear` is available.  
+    /// Non-power-of-two textures are clamped to the edge. See `SamplerPolicy::for_size`.
+    pub fn register_float_texture_from_pixels(
+        &mut self,
+        context: &WebGlRenderingContext,
+        data: &[f32],
+        layout: PixelLayout,
+        id: String,
+        sampler: &SamplerPolicy,
+    ) -> Result<String, Error> {
+        layout.check_data_length(data.len())?;
+        match context.get_extension("OES_texture_float") {
+            Ok(Some(_)) => {}
+            _ => {
+                return Err(Error::Capability(String::from(
+                    "Float textures require the OES_texture_float extension.",
+                )))
+            }
+        }
+        let linear_filtering = context
+            .get_extension("OES_texture_float_linear")
+            .is_ok_and(|extension| extension.is_some());
+        let sampler = sampler
+            .for_size(layout.width, layout.height)
+            .for_float_texture(linear_filtering);
+        self.register_texture_with(context, id, &sampler, |context| unsafe {
+            let float_array = Float32Array::view(data);
+            context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                WebGlRenderingContext::TEXTURE_2D,
+                0,
+                layout.format.get_gl_format() as i32,
+                layout.width as i32,
+                layout.height as i32,
+                0,
+                layout.format.get_gl_format(),
+                WebGlRenderingContext::FLOAT,
+                Some(&float_array),
+            )
+        })
+    }
+
+    /// Creates and binds a new texture, fills it using `upload`, applies `sampler` and
+    /// registers it under `id`.
+    fn register_texture_with<F>(
+        &mut self,
+        context: &WebGlRenderingContext,
+        id: String,
+        sampler: &SamplerPolicy,
+        upload: F,
+    ) -> Result<String, Error>
+    where
+        F: FnOnce(&WebGlRenderingContext) -> Result<(), JsValue>,
+    {
+        match context.create_texture() {
+            None => Err(Error::Load(String::from("Could not create texture"))),
+            Some(texture) => {
+                context.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+                match upload(context) {
+                    Err(_) => Err(Error::Load(String::from("Texture binding failed."))),
                     Ok(_) => {
+                        sampler.apply(context);
                         self.index.insert(id.clone(), self.assets.len());
                         self.assets.push(Asset::Texture(Rc::new(texture)));
                         Ok(id)
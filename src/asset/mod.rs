@@ -3,15 +3,20 @@ mod asset_registry;
 
 pub use asset_registry::AssetRegistry;
 
-use crate::renderer::{Buffer, Material, MaterialInstance, MeshData, Uniform, UniformValue};
+use crate::error::Error;
+use crate::renderer::{
+    Buffer, BufferUsage, Material, MaterialInstance, MeshData, Uniform, UniformValue,
+};
 use bincode::deserialize;
 use web_sys::WebGlRenderingContext;
 use wtvr3d_file::{FileValue, MaterialFile, MaterialInstanceFile, MeshFile, ShaderDataType};
 
-pub fn deserialize_wmesh(context: &WebGlRenderingContext, data: &[u8]) -> Result<MeshData, String> {
+pub fn deserialize_wmesh(context: &WebGlRenderingContext, data: &[u8]) -> Result<MeshData, Error> {
     let mesh_files_result = deserialize::<MeshFile>(data);
     match mesh_files_result {
-        Err(_) => Err(String::from("Could not deserialize the given mesh file.")),
+        Err(_) => Err(Error::Load(String::from(
+            "Could not deserialize the given mesh file.",
+        ))),
         Ok(mesh_file) => Ok(make_mesh_data_from(context, &mesh_file)),
     }
 }
@@ -19,25 +24,25 @@ pub fn deserialize_wmesh(context: &WebGlRenderingContext, data: &[u8]) -> Result
 pub fn deserialize_wmaterial(
     asset_registry: &AssetRegistry,
     data: &[u8],
-) -> Result<Material, String> {
+) -> Result<Material, Error> {
     let material_files_result = deserialize::<MaterialFile>(data);
     match material_files_result {
-        Err(_) => Err(String::from(
+        Err(_) => Err(Error::Load(String::from(
             "Could not deserialize the given material file.",
-        )),
-        Ok(material_file) => Ok(make_material_from(asset_registry, &material_file)),
+        ))),
+        Ok(material_file) => make_material_from(asset_registry, &material_file),
     }
 }
 
 pub fn deserialize_wmatinstance(
     asset_registry: &AssetRegistry,
     data: &[u8],
-) -> Result<MaterialInstance, String> {
+) -> Result<MaterialInstance, Error> {
     let material_files_result = deserialize::<MaterialInstanceFile>(data);
     match material_files_result {
-        Err(_) => Err(String::from(
+        Err(_) => Err(Error::Load(String::from(
             "Could not deserialize the given material file.",
-        )),
+        ))),
         Ok(material_instance_file) => {
             make_material_instance_from(asset_registry, &material_instance_file)
         }
@@ -65,6 +70,7 @@ fn make_mesh_data_from(context: &WebGlRenderingContext, mesh_file: &MeshFile) ->
                 buffer.data_type,
                 buffer_data,
                 indexes,
+                BufferUsage::Static,
             );
             mesh_data.push_buffer(buf);
         }
@@ -72,7 +78,10 @@ fn make_mesh_data_from(context: &WebGlRenderingContext, mesh_file: &MeshFile) ->
     mesh_data
 }
 
-fn make_material_from(asset_registry: &AssetRegistry, mat_file: &MaterialFile) -> Material {
+fn make_material_from(
+    asset_registry: &AssetRegistry,
+    mat_file: &MaterialFile,
+) -> Result<Material, Error> {
     let mut material = Material::new(
         &mat_file.vertex_shader,
         &mat_file.framgent_shader,
@@ -85,18 +94,19 @@ fn make_material_from(asset_registry: &AssetRegistry, mat_file: &MaterialFile) -
                 .unwrap();
         let mut uniform = Uniform::new(uniform_data.0, value);
         if (uniform_data.1).0 == ShaderDataType::Sampler2D {
+            check_texture_unit(asset_registry, &mat_file.id, max_texture)?;
             uniform.set_texture_index(max_texture);
             max_texture += 1;
         }
         material.set_uniform(uniform);
     }
-    material
+    Ok(material)
 }
 
 fn make_material_instance_from(
     asset_registry: &AssetRegistry,
     mat_instance_file: &MaterialInstanceFile,
-) -> Result<MaterialInstance, String> {
+) -> Result<MaterialInstance, Error> {
     match asset_registry.get_material(&mat_instance_file.parent_id) {
         Some(mat) => {
             let mut mat_instance = MaterialInstance::new(mat.clone(), &mat_instance_file.id);
@@ -107,6 +117,7 @@ fn make_material_instance_from(
                     next_index = index + 1;
                 }
             }
+            let mut texture_set = Vec::new();
             for uniform_data in &mat_instance_file.uniforms {
                 let value = make_uniform_value_from(
                     (uniform_data.1).0,
@@ -116,22 +127,46 @@ fn make_material_instance_from(
                 .unwrap();
                 let mut uniform = Uniform::new(uniform_data.0, value);
                 if (uniform_data.1).0 == ShaderDataType::Sampler2D {
+                    if let FileValue::AssetID(texture_id) = &(uniform_data.1).1 {
+                        if let Some(texture_index) = asset_registry.get_id_from_str(texture_id) {
+                            texture_set.push(texture_index);
+                        }
+                    }
                     if parent_texture_indexes.contains_key(uniform_data.0) {
                         uniform.set_texture_index(
                             parent_texture_indexes.get(uniform_data.0).unwrap().clone(),
                         );
                     } else {
+                        check_texture_unit(asset_registry, &mat_instance_file.id, next_index)?;
                         uniform.set_texture_index(next_index);
                         next_index += 1;
                     }
                 }
                 mat_instance.set_uniform(uniform);
             }
+            mat_instance.set_texture_set(texture_set);
             Ok(mat_instance)
         }
-        None => Err(String::from(
+        None => Err(Error::Load(String::from(
             "Could not find parent material. Has it been registered yet?",
-        )),
+        ))),
+    }
+}
+
+/// Checks that texture unit `index` is available, rejecting materials that need
+/// more textures than supported.
+fn check_texture_unit(asset_registry: &AssetRegistry, id: &str, index: u32) -> Result<(), Error> {
+    let available_texture_units = asset_registry.get_available_texture_units();
+    if index < available_texture_units {
+        Ok(())
+    } else {
+        Err(Error::Capability(format!(
+            "{} needs at least {} textures, but only {} texture units are available ({} reserved).",
+            id,
+            index + 1,
+            available_texture_units,
+            asset_registry.get_reserved_texture_units()
+        )))
     }
 }
 
@@ -139,18 +174,54 @@ fn make_uniform_value_from(
     value_type: ShaderDataType,
     fv: &FileValue,
     asset_registry: &AssetRegistry,
-) -> Result<Box<dyn UniformValue>, String> {
+) -> Result<Box<dyn UniformValue>, Error> {
     match fv {
         FileValue::F32Array(fvec) => Ok(Box::new((value_type, fvec.clone()))),
         FileValue::I16Array(ivec) => Ok(Box::new((value_type, ivec.clone()))),
         FileValue::U8Array(uvec) => Ok(Box::new((value_type, uvec.clone()))),
         FileValue::AssetID(id) => match asset_registry.get_texture(&id) {
             Some(rc) => Ok(Box::new(rc)),
-            None => Err(format!(
+            None => Err(Error::Load(format!(
                 "Texture with id {} does not exist. Has it been registered yet?",
                 id
-            )),
+            ))),
         },
-        _ => Err(String::from("Unknown FileValue reached.")),
+        _ => Err(Error::Load(String::from("Unknown FileValue reached."))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_units_below_the_maximum_are_available() {
+        let mut asset_registry = AssetRegistry::new();
+        asset_registry.set_max_texture_units(8);
+        for index in 0..8 {
+            assert!(check_texture_unit(&asset_registry, "material", index).is_ok());
+        }
+    }
+
+    #[test]
+    fn ninth_texture_is_rejected_with_eight_units() {
+        let mut asset_registry = AssetRegistry::new();
+        asset_registry.set_max_texture_units(8);
+        match check_texture_unit(&asset_registry, "material", 8) {
+            Err(Error::Capability(message)) => {
+                assert!(message.contains("needs at least 9 textures"));
+                assert!(message.contains("only 8 texture units"));
+            }
+            _ => panic!("the 9th texture unit should be rejected"),
+        }
+    }
+
+    #[test]
+    fn reserved_texture_units_are_not_available() {
+        let mut asset_registry = AssetRegistry::new();
+        asset_registry.set_max_texture_units(8);
+        asset_registry.set_reserved_texture_units(2);
+        assert!(check_texture_unit(&asset_registry, "material", 5).is_ok());
+        assert!(check_texture_unit(&asset_registry, "material", 6).is_err());
     }
 }
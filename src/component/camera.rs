@@ -1,6 +1,6 @@
 //! Camera component. Used as the point of vue to render the scene.
 
-use nalgebra::{Isometry3, Matrix4, Perspective3, Point3, Vector3};
+use nalgebra::{Isometry3, Matrix4, Perspective3, Point3, Translation3, Vector2, Vector3};
 use specs::{Component, VecStorage};
 
 /// Represents a Camera in the scene, with its projection data.
@@ -14,6 +14,9 @@ pub struct Camera {
     /// ⚠ Will be removed in favor of a normal transform component for the camera
     // ⭕ TODO : move this in a transform component
     view: Isometry3<f32>,
+
+    /// Sub-pixel offset applied to projected points, in normalized device coordinates.
+    jitter: Option<Vector2<f32>>,
 }
 
 impl Camera {
@@ -31,6 +34,7 @@ impl Camera {
         Camera {
             projection: projection,
             view: view,
+            jitter: None,
         }
     }
 
@@ -41,11 +45,35 @@ impl Camera {
 
     /// Getter for the view-projection matrix. Returns None if the `vp_matrix` is marked as `dirty`.
     pub fn get_vp_matrix(&self) -> Matrix4<f32> {
-        self.projection.to_homogeneous() * self.view.to_homogeneous()
+        self.get_projection_matrix() * self.view.to_homogeneous()
     }
 
+    /// Getter for the projection matrix, including the jitter if any.
     pub fn get_projection_matrix(&self) -> Matrix4<f32> {
-        self.projection.to_homogeneous()
+        match self.jitter {
+            Some(jitter) => {
+                Matrix4::new_translation(&Vector3::new(jitter.x, jitter.y, 0.0))
+                    * self.projection.to_homogeneous()
+            }
+            None => self.projection.to_homogeneous(),
+        }
+    }
+
+    /// Offsets every projected point by `jitter`, in normalized device coordinates,
+    /// for temporal anti-aliasing or supersampling. `None` removes the offset.  
+    /// See `Camera::get_halton_jitter` for a sub-pixel sequence.
+    pub fn set_jitter(&mut self, jitter: Option<Vector2<f32>>) {
+        self.jitter = jitter;
+    }
+
+    /// Sub-pixel jitter for frame `frame_index`, following the Halton(2, 3) sequence,
+    /// for a viewport of `width` by `height` pixels.  
+    /// Offsets stay within half a pixel in each direction.
+    pub fn get_halton_jitter(frame_index: u32, width: u32, height: u32) -> Vector2<f32> {
+        Vector2::new(
+            (halton(frame_index + 1, 2) - 0.5) * 2.0 / width as f32,
+            (halton(frame_index + 1, 3) - 0.5) * 2.0 / height as f32,
+        )
     }
     pub fn get_view_matrix(&self) -> Matrix4<f32> {
         self.view.to_homogeneous()
@@ -54,6 +82,49 @@ impl Camera {
     pub fn get_position(&self) -> &Vector3<f32> {
         &self.view.translation.vector
     }
+
+    /// Returns a camera blended between `self` (`t = 0`) and `other` (`t = 1`).  
+    /// The eye position, field of view, aspect ratio and clip planes are interpolated linearly,
+    /// and the orientation is spherically interpolated.
+    pub fn lerp_to(&self, other: &Camera, t: f32) -> Camera {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let eye = self
+            .get_eye_position()
+            .coords
+            .lerp(&other.get_eye_position().coords, t);
+        let rotation = self
+            .view
+            .rotation
+            .try_slerp(&other.view.rotation, t, f32::EPSILON)
+            .unwrap_or(if t < 0.5 {
+                self.view.rotation
+            } else {
+                other.view.rotation
+            });
+        let view = Isometry3::from_parts(Translation3::from(-(rotation * eye)), rotation);
+        let projection = Perspective3::new(
+            lerp(self.projection.aspect(), other.projection.aspect()),
+            lerp(self.projection.fovy(), other.projection.fovy()),
+            lerp(self.projection.znear(), other.projection.znear()),
+            lerp(self.projection.zfar(), other.projection.zfar()),
+        );
+        Camera {
+            projection: projection,
+            view: view,
+            jitter: self.jitter,
+        }
+    }
+
+    /// Position of the camera's eye in world space.
+    pub fn get_eye_position(&self) -> Point3<f32> {
+        self.view.inverse_transform_point(&Point3::origin())
+    }
+
+    /// View matrix of a camera with the same orientation, placed at the origin.  
+    /// Used for camera-relative rendering.
+    pub fn get_view_rotation_matrix(&self) -> Matrix4<f32> {
+        self.view.rotation.to_homogeneous()
+    }
 }
 
 impl Default for Camera {
@@ -72,3 +143,76 @@ impl Default for Camera {
 impl Component for Camera {
     type Storage = VecStorage<Self>;
 }
+
+/// Element `index` of the Halton low-discrepancy sequence in `base`, in `[0, 1)`.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence_matches_known_values() {
+        let base_2: Vec<f32> = (1..5).map(|index| halton(index, 2)).collect();
+        let base_3: Vec<f32> = (1..5).map(|index| halton(index, 3)).collect();
+        assert_eq!(base_2, [0.5, 0.25, 0.75, 0.125]);
+        for (value, expected) in base_3
+            .iter()
+            .zip(&[1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0])
+        {
+            assert!((value - expected).abs() < f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn halton_jitter_stays_within_a_pixel() {
+        for frame_index in 0..64 {
+            let jitter = Camera::get_halton_jitter(frame_index, 800, 600);
+            assert!(jitter.x.abs() <= 1.0 / 800.0);
+            assert!(jitter.y.abs() <= 1.0 / 600.0);
+        }
+    }
+
+    #[test]
+    fn halton_jitter_varies_between_frames() {
+        let first = Camera::get_halton_jitter(0, 800, 600);
+        let second = Camera::get_halton_jitter(1, 800, 600);
+        assert_eq!(first, Vector2::new(0.0, (1.0 / 3.0 - 0.5) * 2.0 / 600.0));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn jitter_offsets_the_projection() {
+        let mut camera = Camera::default();
+        let unjittered = camera.get_projection_matrix();
+        camera.set_jitter(Some(Vector2::new(0.01, -0.02)));
+        let jittered = camera.get_projection_matrix();
+        let point = Point3::new(1.0, 2.0, -10.0);
+        let offset = jittered.transform_point(&point) - unjittered.transform_point(&point);
+        assert!((offset.x - 0.01).abs() < 1e-5);
+        assert!((offset.y + 0.02).abs() < 1e-5);
+        assert!(offset.z.abs() < 1e-5);
+        camera.set_jitter(None);
+        assert_eq!(camera.get_projection_matrix(), unjittered);
+    }
+
+    #[test]
+    fn lerp_midpoint_blends_position_and_field_of_view() {
+        let target = Point3::origin();
+        let from = Camera::new(1.0, 0.5, 0.1, 100.0, &Point3::new(0.0, 0.0, 10.0), &target);
+        let to = Camera::new(1.0, 1.5, 0.1, 100.0, &Point3::new(0.0, 10.0, 10.0), &target);
+        let midpoint = from.lerp_to(&to, 0.5);
+        let eye = midpoint.get_eye_position();
+        assert!((eye - Point3::new(0.0, 5.0, 10.0)).norm() < 1e-4);
+        assert!((midpoint.projection.fovy() - 1.0).abs() < 1e-6);
+    }
+}
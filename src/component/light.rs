@@ -20,6 +20,13 @@ pub struct Cone {
     pub angle: f32,
 }
 
+/// Turns a positioned light into a tube (capsule) light, lit along its transform's local X axis.
+#[derive(Clone)]
+pub struct Tube {
+    pub length: f32,
+    pub radius: f32,
+}
+
 impl Component for Light {
     type Storage = HashMapStorage<Light>;
 }
@@ -31,3 +38,7 @@ impl Component for Direction {
 impl Component for Cone {
     type Storage = HashMapStorage<Cone>;
 }
+
+impl Component for Tube {
+    type Storage = HashMapStorage<Tube>;
+}
@@ -1,5 +1,6 @@
 //! Representation of a mesh in a scene
 
+use crate::error::Error;
 use crate::renderer::{LightConfiguration, Renderer};
 use specs::{Component, VecStorage};
 use std::cell::RefCell;
@@ -11,6 +12,18 @@ pub struct Mesh {
     material: usize,
     material_instance: usize,
     mesh_data: usize,
+
+    /// Offset added to this mesh's depth when sorting transparent objects.
+    /// Positive values make it render as if it were further from the camera.
+    sort_bias: f32,
+
+    /// Range of indices drawn for this mesh, as `(first index, index count)`.
+    /// `None` draws the whole `MeshData`. Used for submeshes sharing a `MeshData` and partial draws.
+    draw_range: Option<(i32, i32)>,
+
+    /// Depth range this mesh is drawn into, as `(near, far)` within `[0, 1]`.
+    /// `None` uses the whole depth range.
+    depth_range: Option<(f32, f32)>,
 }
 
 impl Mesh {
@@ -20,6 +33,9 @@ impl Mesh {
             mesh_data: mesh_data_id,
             material: material_id,
             material_instance: material_instance_id,
+            sort_bias: 0.0,
+            draw_range: None,
+            depth_range: None,
         }
     }
     /// Getter for material
@@ -37,12 +53,71 @@ impl Mesh {
         &self.mesh_data
     }
 
+    /// Getter for sort_bias
+    pub fn get_sort_bias(&self) -> f32 {
+        self.sort_bias
+    }
+
+    /// Sets the offset added to this mesh's depth when sorting transparent objects.  
+    /// Use it to force a transparent mesh to be drawn before (positive) or after (negative)
+    /// others when depth sorting by center gives the wrong order.
+    pub fn set_sort_bias(&mut self, sort_bias: f32) {
+        self.sort_bias = sort_bias;
+    }
+
+    /// Getter for draw_range
+    pub fn get_draw_range(&self) -> Option<(i32, i32)> {
+        self.draw_range
+    }
+
+    /// Restricts drawing to a `(first index, index count)` range of the `MeshData`'s indices,
+    /// or draws all of them with `None`.  
+    /// Lets several meshes draw different material groups of the same `MeshData`, or
+    /// reveal part of a mesh without uploading new data.  
+    /// The range must fit in the `vertex_count` indices of the `MeshData`. If the `MeshData`
+    /// later shrinks, the range is clamped at draw time. See `get_clamped_draw_range`.
+    pub fn set_draw_range(
+        &mut self,
+        draw_range: Option<(i32, i32)>,
+        vertex_count: i32,
+    ) -> Result<(), Error> {
+        if let Some((first_index, index_count)) = draw_range {
+            check_draw_range(first_index, index_count, vertex_count)?;
+        }
+        self.draw_range = draw_range;
+        Ok(())
+    }
+
+    /// Returns the `(first index, index count)` range to draw from a `MeshData` with
+    /// `vertex_count` indices, clamped so that it never goes past its end.
+    pub fn get_clamped_draw_range(&self, vertex_count: i32) -> (i32, i32) {
+        let vertex_count = vertex_count.max(0);
+        let (first_index, index_count) = self.draw_range.unwrap_or((0, vertex_count));
+        let first_index = first_index.clamp(0, vertex_count);
+        (
+            first_index,
+            index_count.clamp(0, vertex_count - first_index),
+        )
+    }
+
+    /// Getter for depth_range
+    pub fn get_depth_range(&self) -> Option<(f32, f32)> {
+        self.depth_range
+    }
+
+    /// Draws this mesh into the `[near, far]` slice of the depth buffer.  
+    /// Compressing UI meshes into e.g. `[0, 0.1]` keeps them in front of the world
+    /// while still depth testing against each other.
+    pub fn set_depth_range(&mut self, depth_range: Option<(f32, f32)>) {
+        self.depth_range = depth_range;
+    }
+
     /// Compiles the material and fetches all the necessary uniform and attribute locations
     pub fn compile_material(
         &self,
         renderer_ref: Rc<RefCell<Renderer>>,
         light_config: &LightConfiguration,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let renderer = renderer_ref.borrow();
         if let Some(material_rc) = renderer
             .get_asset_registry()
@@ -50,6 +125,20 @@ impl Mesh {
         {
             {
                 let mut material = material_rc.borrow_mut();
+                if !material.uses_vertex_ao() {
+                    if let Some(mesh_data) = renderer
+                        .get_asset_registry()
+                        .get_mesh_data_with_index(self.mesh_data)
+                    {
+                        if mesh_data
+                            .borrow()
+                            .get_buffer(crate::utils::constants::AO_BUFFER_NAME)
+                            .is_some()
+                        {
+                            material.set_vertex_ao(true);
+                        }
+                    }
+                }
                 if material.should_compile(light_config) {
                     match material.compile(renderer.get_webgl_context(), light_config) {
                         Err(message) => {
@@ -69,7 +158,9 @@ impl Mesh {
                     .lookup_locations(renderer.get_webgl_context(), material_rc.clone());
             }
         } else {
-            return Err("Material could not be found. Has it been registered yet?".to_owned());
+            return Err(Error::Load(
+                "Material could not be found. Has it been registered yet?".to_owned(),
+            ));
         }
         if let Some(material_instance_rc) = renderer
             .get_asset_registry()
@@ -78,9 +169,9 @@ impl Mesh {
             let mut material_instance = material_instance_rc.borrow_mut();
             material_instance.lookup_locations(renderer.get_webgl_context(), light_config);
         } else {
-            return Err(
+            return Err(Error::Load(
                 "Material Instance could not be found. Has it been registered yet?".to_owned(),
-            );
+            ));
         }
         Ok(())
     }
@@ -89,3 +180,59 @@ impl Mesh {
 impl Component for Mesh {
     type Storage = VecStorage<Self>;
 }
+
+/// Checks that a `(first index, index count)` draw range fits in a `MeshData`
+/// with `vertex_count` indices.
+pub fn check_draw_range(
+    first_index: i32,
+    index_count: i32,
+    vertex_count: i32,
+) -> Result<(), Error> {
+    if first_index < 0 || index_count < 0 || first_index + index_count > vertex_count {
+        Err(Error::Load(format!(
+            "Draw range {}..{} is out of bounds for mesh data with {} indices.",
+            first_index,
+            first_index + index_count,
+            vertex_count
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_mesh_data_is_drawn_by_default() {
+        let mesh = Mesh::new(0, 0, 0);
+        assert_eq!(mesh.get_clamped_draw_range(36), (0, 36));
+    }
+
+    #[test]
+    fn draw_range_limits_the_index_count() {
+        let mut mesh = Mesh::new(0, 0, 0);
+        assert!(mesh.set_draw_range(Some((6, 12)), 36).is_ok());
+        assert_eq!(mesh.get_clamped_draw_range(36), (6, 12));
+    }
+
+    #[test]
+    fn invalid_draw_ranges_are_rejected() {
+        let mut mesh = Mesh::new(0, 0, 0);
+        assert!(mesh.set_draw_range(Some((0, -3)), 36).is_err());
+        assert!(mesh.set_draw_range(Some((-1, 3)), 36).is_err());
+        assert!(mesh.set_draw_range(Some((40, 0)), 36).is_err());
+        assert!(mesh.set_draw_range(Some((30, 12)), 36).is_err());
+        assert_eq!(mesh.get_draw_range(), None);
+    }
+
+    #[test]
+    fn draw_range_is_clamped_when_mesh_data_shrinks() {
+        let mut mesh = Mesh::new(0, 0, 0);
+        mesh.set_draw_range(Some((24, 12)), 36).unwrap();
+        assert_eq!(mesh.get_clamped_draw_range(30), (24, 6));
+        assert_eq!(mesh.get_clamped_draw_range(12), (12, 0));
+        assert_eq!(mesh.get_clamped_draw_range(-1), (0, 0));
+    }
+}
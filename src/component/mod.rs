@@ -6,6 +6,6 @@ mod mesh;
 mod transform;
 
 pub use camera::Camera;
-pub use light::{Cone, Direction, Light};
-pub use mesh::Mesh;
+pub use light::{Cone, Direction, Light, Tube};
+pub use mesh::{check_draw_range, Mesh};
 pub use transform::{DirtyTransform, Enabled, Transform, TransformParent};
@@ -51,12 +51,18 @@ impl Transform {
         self.local_scale = new_scale.clone();
     }
 
-    /// Re-computes world matrix from its inner properties and a given parent world matrix.
-    pub fn refresh_world_matrix(&mut self, parent_world_matrix: Option<Matrix4<f32>>) -> () {
+    /// Computes the local matrix of this Transform: scale, then rotation, then translation.  
+    /// It is not cached; dirtiness is tracked with the `DirtyTransform` component instead.
+    pub fn get_local_matrix(&self) -> Matrix4<f32> {
         let scale_matrix = Matrix4::new_nonuniform_scaling(&self.local_scale);
         let isometry =
             Isometry3::from_parts(self.local_translation.clone(), self.local_rotation.clone());
-        let local_matrix = isometry.to_homogeneous() * scale_matrix;
+        isometry.to_homogeneous() * scale_matrix
+    }
+
+    /// Re-computes world matrix from its inner properties and a given parent world matrix.
+    pub fn refresh_world_matrix(&mut self, parent_world_matrix: Option<Matrix4<f32>>) -> () {
+        let local_matrix = self.get_local_matrix();
         if let Some(parent_matrix) = parent_world_matrix {
             self.world_matrix = parent_matrix * local_matrix;
         } else {
@@ -114,3 +120,20 @@ impl Component for Enabled {
 impl Component for DirtyTransform {
     type Storage = NullStorage<Self>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translation_only_local_matrix_ends_with_the_translation() {
+        let translation = Vector3::new(1.0, -2.0, 3.5);
+        let transform = Transform::new(
+            &translation,
+            &Vector3::zeros(),
+            &Vector3::new(1.0, 1.0, 1.0),
+        );
+        let local_matrix = transform.get_local_matrix();
+        assert_eq!(local_matrix.column(3).into_owned(), translation.push(1.0));
+    }
+}
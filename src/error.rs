@@ -0,0 +1,108 @@
+//! Error type shared by every fallible operation in wtvr3d.
+
+use std::error;
+use std::fmt;
+
+/// ## Error
+///
+/// Error returned by wtvr3d's fallible operations.
+/// Plain `String` messages convert into `Error::Other`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A shader failed to compile. Contains the shader info log.
+    ShaderCompile(String),
+
+    /// A program failed to link. Contains the program info log.
+    ShaderLink(String),
+
+    /// A uniform value could not be uploaded to the context.
+    Uniform(String),
+
+    /// The WebGL context was lost.
+    ContextLost,
+
+    /// An asset could not be parsed, created or found.
+    Load(String),
+
+    /// A WebGL capability or extension needed for the operation is missing.
+    Capability(String),
+
+    /// Any other error.
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ShaderCompile(log) => write!(f, "Shader compilation failed: {}", log),
+            Error::ShaderLink(log) => write!(f, "Program linking failed: {}", log),
+            Error::Uniform(message) => write!(f, "Uniform error: {}", message),
+            Error::ContextLost => write!(f, "The WebGL context was lost"),
+            Error::Load(message) => write!(f, "Loading error: {}", message),
+            Error::Capability(message) => write!(f, "Missing capability: {}", message),
+            Error::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Other(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::Other(message.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_variant_formats_its_details() {
+        let cases = vec![
+            (
+                Error::ShaderCompile(String::from("0:12: 'u_foo' : undeclared identifier")),
+                "Shader compilation failed: 0:12: 'u_foo' : undeclared identifier",
+            ),
+            (
+                Error::ShaderLink(String::from("varyings do not match")),
+                "Program linking failed: varyings do not match",
+            ),
+            (
+                Error::Uniform(String::from("wrong value size")),
+                "Uniform error: wrong value size",
+            ),
+            (Error::ContextLost, "The WebGL context was lost"),
+            (
+                Error::Load(String::from("mesh is missing")),
+                "Loading error: mesh is missing",
+            ),
+            (
+                Error::Capability(String::from("OES_texture_float")),
+                "Missing capability: OES_texture_float",
+            ),
+            (Error::Other(String::from("oops")), "oops"),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn strings_convert_into_other() {
+        assert_eq!(
+            Error::from("message"),
+            Error::Other(String::from("message"))
+        );
+        assert_eq!(
+            Error::from(String::from("message")),
+            Error::Other(String::from("message"))
+        );
+    }
+}
@@ -6,6 +6,7 @@
 
 pub mod asset;
 pub mod component;
+pub mod error;
 pub mod renderer;
 pub mod scene;
 pub mod system;
@@ -1,6 +1,7 @@
 //! Interface and implementations for managing WebGL Buffers and Attributes.
 
-use js_sys::{Float32Array, Uint16Array};
+use crate::error::Error;
+use js_sys::{Float32Array, Uint16Array, Uint32Array};
 use std::rc::Rc;
 use web_sys::{WebGlBuffer, WebGlRenderingContext};
 use wtvr3d_file::ShaderDataType;
@@ -27,6 +28,12 @@ pub struct Buffer {
     /// Numeric type (automatically set); can be Float32, Int16, and UInt8.
     number_type: u32,
 
+    /// Type of the indices in the index buffer: `UNSIGNED_SHORT` or `UNSIGNED_INT`.
+    index_type: u32,
+
+    /// Usage hint given to the driver each time the data is uploaded.
+    usage: BufferUsage,
+
     /// Custom stride to be used when setting the attribute pointer
     pub stride: i32,
 
@@ -41,6 +48,7 @@ impl Buffer {
         data_type: ShaderDataType,
         data: &[f32],
         indexes: Option<&[u16]>,
+        usage: BufferUsage,
     ) -> Buffer {
         let gl_buffer = context.create_buffer().unwrap();
         context.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&gl_buffer));
@@ -50,7 +58,7 @@ impl Buffer {
             context.buffer_data_with_array_buffer_view(
                 WebGlRenderingContext::ARRAY_BUFFER,
                 &float_array,
-                WebGlRenderingContext::STATIC_DRAW,
+                usage.get_gl_usage(),
             );
         }
 
@@ -83,10 +91,115 @@ impl Buffer {
             stride: 0,
             offset: 0,
             number_type: WebGlRenderingContext::FLOAT,
+            index_type: WebGlRenderingContext::UNSIGNED_SHORT,
+            usage,
         }
     }
 
+    /// Re-uploads the attribute data of this `Buffer`, using its usage hint.  
+    /// Meant for buffers created with `BufferUsage::Dynamic` or `BufferUsage::Stream`.
+    pub fn set_data(&self, context: &WebGlRenderingContext, data: &[f32]) {
+        context.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.value));
+        unsafe {
+            let float_array = Float32Array::view(data);
+            context.buffer_data_with_array_buffer_view(
+                WebGlRenderingContext::ARRAY_BUFFER,
+                &float_array,
+                self.usage.get_gl_usage(),
+            );
+        }
+    }
+
+    /// Deletes the underlying `WebGlBuffer` and index buffer if no other `Buffer` shares them.
+    pub fn delete(&self, context: &WebGlRenderingContext) {
+        if Rc::strong_count(&self.value) == 1 {
+            context.delete_buffer(Some(&self.value));
+        }
+        if let Some(index_buffer) = &self.indexes {
+            if Rc::strong_count(index_buffer) == 1 {
+                context.delete_buffer(Some(index_buffer));
+            }
+        }
+    }
+
+    /// Getter for `usage`
+    pub fn get_usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Replaces the indices of this `Buffer`, using its usage hint.  
+    /// Indices are uploaded as `UNSIGNED_SHORT` when they all fit in 16 bits, and as
+    /// `UNSIGNED_INT` otherwise, which requires the `OES_element_index_uint` extension.  
+    /// The existing index buffer is reused unless other `Buffer`s share it.
+    pub fn set_indices(
+        &mut self,
+        context: &WebGlRenderingContext,
+        indices: &[u32],
+    ) -> Result<(), Error> {
+        let index_type = get_index_type_for(indices);
+        let fits_u16 = index_type == WebGlRenderingContext::UNSIGNED_SHORT;
+        if !fits_u16 {
+            match context.get_extension("OES_element_index_uint") {
+                Ok(Some(_)) => {}
+                _ => {
+                    return Err(Error::Capability(String::from(
+                        "Indices above 65535 require the OES_element_index_uint extension.",
+                    )))
+                }
+            }
+        }
+        let gl_index_buffer = match &self.indexes {
+            Some(index_buffer) if Rc::strong_count(index_buffer) == 1 => index_buffer.clone(),
+            _ => Rc::new(
+                context
+                    .create_buffer()
+                    .ok_or_else(|| Error::Load(String::from("Could not create index buffer")))?,
+            ),
+        };
+        context.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&gl_index_buffer),
+        );
+        if fits_u16 {
+            let short_indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+            unsafe {
+                let uint_array = Uint16Array::view(&short_indices);
+                context.buffer_data_with_array_buffer_view(
+                    WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+                    &uint_array,
+                    self.usage.get_gl_usage(),
+                );
+            }
+        } else {
+            unsafe {
+                let uint_array = Uint32Array::view(indices);
+                context.buffer_data_with_array_buffer_view(
+                    WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+                    &uint_array,
+                    self.usage.get_gl_usage(),
+                );
+            }
+        }
+        self.index_type = index_type;
+        self.indexes = Some(gl_index_buffer);
+        Ok(())
+    }
+
+    /// Returns `true` if this `Buffer` has an index buffer
+    pub fn has_indices(&self) -> bool {
+        self.indexes.is_some()
+    }
+
+    /// Returns the type of the indices in the index buffer, to be used with `draw_elements`.
+    pub fn get_index_type(&self) -> u32 {
+        self.index_type
+    }
+
     /// Returns the attribute name for this buffer
+    pub fn get_data_type(&self) -> &ShaderDataType {
+        &self.data_type
+    }
+
     pub fn get_attribute_name(&self) -> &str {
         self.attribute_name.as_str()
     }
@@ -115,3 +228,85 @@ impl Buffer {
         }
     }
 }
+
+/// Returns the smallest index type able to hold every index: `UNSIGNED_SHORT` when they
+/// all fit in 16 bits, `UNSIGNED_INT` otherwise.
+pub fn get_index_type_for(indices: &[u32]) -> u32 {
+    if indices.iter().all(|&index| index <= u32::from(u16::MAX)) {
+        WebGlRenderingContext::UNSIGNED_SHORT
+    } else {
+        WebGlRenderingContext::UNSIGNED_INT
+    }
+}
+
+/// ## BufferUsage
+///
+/// Hint telling the driver how often a buffer's data changes, so that it can pick
+/// the most suitable memory for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferUsage {
+    /// Uploaded once and drawn many times, like the positions of a rigid mesh.
+    Static,
+    /// Updated repeatedly and drawn many times, like per-instance colors.
+    Dynamic,
+    /// Updated every time before being drawn once or a few times.
+    Stream,
+}
+
+impl BufferUsage {
+    /// WebGL usage constant for this hint
+    pub fn get_gl_usage(&self) -> u32 {
+        match self {
+            BufferUsage::Static => WebGlRenderingContext::STATIC_DRAW,
+            BufferUsage::Dynamic => WebGlRenderingContext::DYNAMIC_DRAW,
+            BufferUsage::Stream => WebGlRenderingContext::STREAM_DRAW,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_indices_use_unsigned_short() {
+        let indices: Vec<u32> = (0..65_000).collect();
+        assert_eq!(
+            get_index_type_for(&indices),
+            WebGlRenderingContext::UNSIGNED_SHORT
+        );
+        assert_eq!(
+            get_index_type_for(&[0, 1, 65_535]),
+            WebGlRenderingContext::UNSIGNED_SHORT
+        );
+    }
+
+    #[test]
+    fn large_indices_use_unsigned_int() {
+        let indices: Vec<u32> = (0..70_000).collect();
+        assert_eq!(
+            get_index_type_for(&indices),
+            WebGlRenderingContext::UNSIGNED_INT
+        );
+        assert_eq!(
+            get_index_type_for(&[0, 1, 65_536]),
+            WebGlRenderingContext::UNSIGNED_INT
+        );
+    }
+
+    #[test]
+    fn usage_hints_map_to_their_gl_constant() {
+        assert_eq!(
+            BufferUsage::Static.get_gl_usage(),
+            WebGlRenderingContext::STATIC_DRAW
+        );
+        assert_eq!(
+            BufferUsage::Dynamic.get_gl_usage(),
+            WebGlRenderingContext::DYNAMIC_DRAW
+        );
+        assert_eq!(
+            BufferUsage::Stream.get_gl_usage(),
+            WebGlRenderingContext::STREAM_DRAW
+        );
+    }
+}
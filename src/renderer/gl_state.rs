@@ -0,0 +1,176 @@
+//! Snapshot of the WebGL state the renderer relies on, to interoperate with external GL code.
+
+use js_sys::{Array, Int32Array};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{WebGlBuffer, WebGlProgram, WebGlRenderingContext, WebGlTexture};
+
+/// Capabilities saved and restored by a `GlState`
+const CAPABILITIES: [u32; 4] = [
+    WebGlRenderingContext::BLEND,
+    WebGlRenderingContext::CULL_FACE,
+    WebGlRenderingContext::DEPTH_TEST,
+    WebGlRenderingContext::STENCIL_TEST,
+];
+
+/// ## GlState
+///
+/// Bound program, enabled capabilities, blend, depth, color mask, stencil and viewport settings,
+/// active texture unit with its bound `TEXTURE_2D`, and bound buffers of a context at the time
+/// it was captured.
+/// Stencil settings are those of front faces, and are restored for both faces.
+pub struct GlState {
+    program: Option<WebGlProgram>,
+    capabilities: Vec<bool>,
+    blend_func: [u32; 4],
+    depth_func: u32,
+    depth_mask: bool,
+    color_mask: [bool; 4],
+    stencil_func: (u32, i32, u32),
+    stencil_op: [u32; 3],
+    stencil_mask: u32,
+    viewport: [i32; 4],
+    active_texture: u32,
+    texture: Option<WebGlTexture>,
+    array_buffer: Option<WebGlBuffer>,
+    element_array_buffer: Option<WebGlBuffer>,
+}
+
+impl GlState {
+    /// Reads the current state of `context`.
+    pub fn capture(context: &WebGlRenderingContext) -> GlState {
+        let parameter = |name| context.get_parameter(name).ok();
+        let number = |name, default: u32| {
+            parameter(name)
+                .and_then(|value: JsValue| value.as_f64())
+                .map_or(default, |value| value as u32)
+        };
+        GlState {
+            program: parameter(WebGlRenderingContext::CURRENT_PROGRAM)
+                .and_then(|value| value.dyn_into().ok()),
+            capabilities: CAPABILITIES
+                .iter()
+                .map(|capability| context.is_enabled(*capability))
+                .collect(),
+            blend_func: [
+                number(
+                    WebGlRenderingContext::BLEND_SRC_RGB,
+                    WebGlRenderingContext::ONE,
+                ),
+                number(
+                    WebGlRenderingContext::BLEND_DST_RGB,
+                    WebGlRenderingContext::ZERO,
+                ),
+                number(
+                    WebGlRenderingContext::BLEND_SRC_ALPHA,
+                    WebGlRenderingContext::ONE,
+                ),
+                number(
+                    WebGlRenderingContext::BLEND_DST_ALPHA,
+                    WebGlRenderingContext::ZERO,
+                ),
+            ],
+            depth_func: number(
+                WebGlRenderingContext::DEPTH_FUNC,
+                WebGlRenderingContext::LESS,
+            ),
+            depth_mask: parameter(WebGlRenderingContext::DEPTH_WRITEMASK)
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            color_mask: parameter(WebGlRenderingContext::COLOR_WRITEMASK)
+                .and_then(|value| value.dyn_into::<Array>().ok())
+                .map_or([true; 4], |mask| {
+                    let channel = |index| mask.get(index).as_bool().unwrap_or(true);
+                    [channel(0), channel(1), channel(2), channel(3)]
+                }),
+            stencil_func: (
+                number(
+                    WebGlRenderingContext::STENCIL_FUNC,
+                    WebGlRenderingContext::ALWAYS,
+                ),
+                number(WebGlRenderingContext::STENCIL_REF, 0) as i32,
+                number(WebGlRenderingContext::STENCIL_VALUE_MASK, u32::MAX),
+            ),
+            stencil_op: [
+                number(
+                    WebGlRenderingContext::STENCIL_FAIL,
+                    WebGlRenderingContext::KEEP,
+                ),
+                number(
+                    WebGlRenderingContext::STENCIL_PASS_DEPTH_FAIL,
+                    WebGlRenderingContext::KEEP,
+                ),
+                number(
+                    WebGlRenderingContext::STENCIL_PASS_DEPTH_PASS,
+                    WebGlRenderingContext::KEEP,
+                ),
+            ],
+            stencil_mask: number(WebGlRenderingContext::STENCIL_WRITEMASK, u32::MAX),
+            viewport: parameter(WebGlRenderingContext::VIEWPORT)
+                .and_then(|value| value.dyn_into::<Int32Array>().ok())
+                .map_or([0; 4], |viewport| {
+                    let mut values = [0; 4];
+                    viewport.copy_to(&mut values);
+                    values
+                }),
+            active_texture: number(
+                WebGlRenderingContext::ACTIVE_TEXTURE,
+                WebGlRenderingContext::TEXTURE0,
+            ),
+            texture: parameter(WebGlRenderingContext::TEXTURE_BINDING_2D)
+                .and_then(|value| value.dyn_into().ok()),
+            array_buffer: parameter(WebGlRenderingContext::ARRAY_BUFFER_BINDING)
+                .and_then(|value| value.dyn_into().ok()),
+            element_array_buffer: parameter(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER_BINDING)
+                .and_then(|value| value.dyn_into().ok()),
+        }
+    }
+
+    /// Applies this state back to `context`.
+    pub fn restore(&self, context: &WebGlRenderingContext) {
+        context.use_program(self.program.as_ref());
+        for (capability, enabled) in CAPABILITIES.iter().zip(&self.capabilities) {
+            if *enabled {
+                context.enable(*capability);
+            } else {
+                context.disable(*capability);
+            }
+        }
+        let [src_rgb, dst_rgb, src_alpha, dst_alpha] = self.blend_func;
+        context.blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+        context.depth_func(self.depth_func);
+        context.depth_mask(self.depth_mask);
+        let [red, green, blue, alpha] = self.color_mask;
+        context.color_mask(red, green, blue, alpha);
+        let (func, reference, mask) = self.stencil_func;
+        context.stencil_func(func, reference, mask);
+        let [fail, depth_fail, pass] = self.stencil_op;
+        context.stencil_op(fail, depth_fail, pass);
+        context.stencil_mask(self.stencil_mask);
+        let [x, y, width, height] = self.viewport;
+        context.viewport(x, y, width, height);
+        context.active_texture(self.active_texture);
+        context.bind_texture(WebGlRenderingContext::TEXTURE_2D, self.texture.as_ref());
+        context.bind_buffer(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            self.array_buffer.as_ref(),
+        );
+        context.bind_buffer(
+            WebGlRenderingContext::ELEMENT_ARRAY_BUFFER,
+            self.element_array_buffer.as_ref(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::get_capability_states;
+
+    #[test]
+    fn capabilities_changed_by_the_renderer_are_saved() {
+        assert!(CAPABILITIES.contains(&WebGlRenderingContext::BLEND));
+        for (capability, _) in &get_capability_states(true, true, false) {
+            assert!(CAPABILITIES.contains(capability));
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use crate::component::{Cone, Light};
-use crate::renderer::{Material, Uniform};
+use crate::component::{Cone, Light, Tube};
+use crate::error::Error;
+use crate::renderer::{LightUniformLocations, Material, Uniform};
 use nalgebra::{Vector3, Vector4};
-use std::cell::{Ref, RefCell};
+use std::cell::RefCell;
 use std::rc::Rc;
 use web_sys::WebGlRenderingContext;
 
@@ -11,6 +12,7 @@ pub struct LightConfiguration {
     pub directional: usize,
     pub point: usize,
     pub spot: usize,
+    pub tube: usize,
 }
 
 /// Resource for sharing light information between the light system and the rendering system
@@ -20,19 +22,28 @@ pub struct LightRepository {
     pub directional: Vec<(Light, Vector3<f32>)>,
     pub point: Vec<(Light, Vector3<f32>)>,
     pub spot: Vec<(Light, Vector3<f32>, Vector3<f32>, Cone)>,
+    /// Tube lights with the world positions of both ends of their segment
+    pub tube: Vec<(Light, Vector3<f32>, Vector3<f32>, Tube)>,
 }
 
 impl LightRepository {
+    /// Sets the light uniforms of `material`.  
+    /// Point and tube light positions are uploaded relative to `origin`.  
+    /// Exactly as many lights as the material was compiled for are uploaded: extra lights are
+    /// ignored, and missing ones are padded with black, zero-intensity lights so that stale
+    /// values are not reused.
     pub fn set_material_uniforms(
         &self,
         context: &WebGlRenderingContext,
         material: Rc<RefCell<Material>>,
-    ) {
+        origin: &Vector3<f32>,
+    ) -> Result<(), Error> {
         let mat = material.borrow();
+        let mut result = Ok(());
         if let Some(light) = &self.ambiant {
             let ambiant_loc = &mat.global_uniform_locations.ambiant_light_location;
             let ambiant_uniform = Uniform::new_with_location(
-                "",
+                crate::utils::constants::AMBIANT_LIGHT_NAME,
                 ambiant_loc.clone(),
                 Box::new(Vector4::new(
                     light.color.x,
@@ -41,62 +52,136 @@ impl LightRepository {
                     light.intensity,
                 )),
             );
-            ambiant_uniform.set_to_context(context).ok();
+            result = result.and(ambiant_uniform.set_to_context(context));
         }
 
-        for (i, dir_light) in self.directional.iter().enumerate() {
-            LightRepository::set_light_uniform(context, &mat, &dir_light.0, false, dir_light.1, i)
+        let unlit = Light {
+            color: Vector3::zeros(),
+            intensity: 0.0,
+            attenuation: 0.0,
+        };
+        let locations = &mat.global_uniform_locations;
+        let directional_locations = &locations.directional_lights_locations;
+        for (light_locations, light) in directional_locations
+            .iter()
+            .zip(pad_lights(&self.directional, directional_locations.len()))
+        {
+            let light_result = match light {
+                Some((light, direction)) => {
+                    LightRepository::set_light_uniform(context, light_locations, light, *direction)
+                }
+                None => LightRepository::set_light_uniform(
+                    context,
+                    light_locations,
+                    &unlit,
+                    -Vector3::y(),
+                ),
+            };
+            result = result.and(light_result);
+        }
+        let point_locations = &locations.point_lights_locations;
+        for (light_locations, light) in point_locations
+            .iter()
+            .zip(pad_lights(&self.point, point_locations.len()))
+        {
+            let light_result = match light {
+                Some((light, position)) => LightRepository::set_light_uniform(
+                    context,
+                    light_locations,
+                    light,
+                    position - origin,
+                ),
+                None => LightRepository::set_light_uniform(
+                    context,
+                    light_locations,
+                    &unlit,
+                    Vector3::zeros(),
+                ),
+            };
+            result = result.and(light_result);
         }
-        for (i, point_light) in self.point.iter().enumerate() {
-            LightRepository::set_light_uniform(
+        let tube_lights_locations = &locations.tube_lights_locations;
+        for (tube_locations, tube_light) in tube_lights_locations
+            .iter()
+            .zip(pad_lights(&self.tube, tube_lights_locations.len()))
+        {
+            let (light, start, end, radius) = match tube_light {
+                Some((light, start, end, tube)) => {
+                    (light, start - origin, end - origin, tube.radius)
+                }
+                None => (&unlit, Vector3::zeros(), Vector3::zeros(), 0.0),
+            };
+            result = result.and(LightRepository::set_light_uniform(
                 context,
-                &mat,
-                &point_light.0,
-                true,
-                point_light.1,
-                i,
-            )
+                &tube_locations.light,
+                light,
+                start,
+            ));
+            let end_uniform = Uniform::new_with_location(
+                crate::utils::constants::TUBE_LIGHT_END_NAME,
+                tube_locations.end.clone(),
+                Box::new(end),
+            );
+            result = result.and(end_uniform.set_to_context(context));
+            let radius_uniform = Uniform::new_with_location(
+                crate::utils::constants::TUBE_LIGHT_RADIUS_NAME,
+                tube_locations.radius.clone(),
+                Box::new(radius),
+            );
+            result = result.and(radius_uniform.set_to_context(context));
         }
+        result
     }
 
     fn set_light_uniform(
         context: &WebGlRenderingContext,
-        material: &Ref<Material>,
+        locations: &LightUniformLocations,
         light: &Light,
-        point: bool,
         dir_or_pos: Vector3<f32>,
-        index: usize,
-    ) -> () {
-        let locations = if point {
-            &material.global_uniform_locations.point_lights_locations
-        } else {
-            &material
-                .global_uniform_locations
-                .directional_lights_locations
-        };
+    ) -> Result<(), Error> {
         let color_uniform = Uniform::new_with_location(
-            "",
-            locations[index].color.clone(),
+            crate::utils::constants::LIGHT_COLOR_NAME,
+            locations.color.clone(),
             Box::new(Vector3::new(light.color.x, light.color.y, light.color.z)),
         );
-        color_uniform.set_to_context(context).ok();
         let intensity_uniform = Uniform::new_with_location(
-            "",
-            locations[index].intensity.clone(),
+            crate::utils::constants::LIGHT_INTENSITY_NAME,
+            locations.intensity.clone(),
             Box::new(light.intensity),
         );
-        intensity_uniform.set_to_context(context).ok();
         let attenuation_uniform = Uniform::new_with_location(
-            "",
-            locations[index].attenuation.clone(),
+            crate::utils::constants::LIGHT_ATTENUATION_NAME,
+            locations.attenuation.clone(),
             Box::new(light.attenuation),
         );
-        attenuation_uniform.set_to_context(context).ok();
         let dir_pos_uniform = Uniform::new_with_location(
-            "",
-            locations[index].position_or_direction.clone(),
+            crate::utils::constants::LIGHT_POSITION_DIRECTION_NAME,
+            locations.position_or_direction.clone(),
             Box::new(dir_or_pos),
         );
-        dir_pos_uniform.set_to_context(context).ok();
+        color_uniform
+            .set_to_context(context)
+            .and(intensity_uniform.set_to_context(context))
+            .and(attenuation_uniform.set_to_context(context))
+            .and(dir_pos_uniform.set_to_context(context))
+    }
+}
+
+/// Returns exactly `count` lights for a shader compiled for `count` lights of a type:
+/// the first lights of `lights`, padded with `None`.
+fn pad_lights<T>(lights: &[T], count: usize) -> Vec<Option<&T>> {
+    (0..count).map(|index| lights.get(index)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_arrays_match_the_compiled_size() {
+        let lights = [1, 2];
+        assert_eq!(pad_lights(&lights, 4), [Some(&1), Some(&2), None, None]);
+        assert_eq!(pad_lights(&lights, 1), [Some(&1)]);
+        assert!(pad_lights(&lights, 0).is_empty());
     }
 }
@@ -7,13 +7,16 @@
 //! while `MaterialInstance` can use the same underlying Material with
 //! different uniform and buffer values.
 
+use super::stencil::StencilConfig;
 use super::uniform::{GlobalUniformLocations, Uniform};
 use super::LightConfiguration;
+use crate::error::Error;
 use crate::utils::console_warn;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader};
+use wtvr3d_file::ShaderDataType;
 
 /// ## Material
 ///
@@ -40,6 +43,9 @@ pub struct Material {
     /// Buffers configuration, with common buffer names and locations.
     attribute_locations: HashMap<String, i32>,
 
+    /// Active attributes of the linked program with their types, read after each compilation.
+    expected_attributes: Vec<(String, ShaderDataType)>,
+
     /// Uniforms shared accross all `MaterialInstance`s sharing this parent material.  
     /// Can be overriden in `MaterialInstance` uniforms if needed.
     shared_uniforms: Vec<(String, Uniform)>,
@@ -55,6 +61,39 @@ pub struct Material {
 
     /// Location lookup state to avoid doing it each frame once it has been done once.
     lookup_done: bool,
+
+    /// Incremented each time a new program is linked, so that `MaterialInstance`s know
+    /// their uniform locations are stale.
+    program_version: u32,
+
+    /// Light configuration for which the last compilation failed, to avoid retrying it each frame.
+    failed_light_configuration: Option<LightConfiguration>,
+
+    /// Float precision forced in the fragment shader. `None` keeps the shader's own declaration.
+    precision: Option<Precision>,
+
+    /// if `true`, normals of back faces are flipped in the fragment shader for double-sided lighting.
+    flip_backface_normals: bool,
+
+    /// if `true`, `USE_AO` is defined in both shaders to read baked ambient occlusion from `a_ao`.
+    vertex_ao: bool,
+
+    /// Float constants defined in both shaders, such as ranges or cutoffs.
+    float_defines: Vec<(String, f32)>,
+
+    /// if `true`, the shader sources changed since the last successful compilation.
+    needs_recompilation: bool,
+
+    /// Stencil settings applied while drawing meshes using this material.
+    stencil: Option<StencilConfig>,
+
+    /// Cheap material drawing this material's opaque meshes during the depth prepass.
+    /// See `get_depth_material`.
+    depth_material: Option<Rc<RefCell<Material>>>,
+
+    /// if `true`, a depth material is created each time this material is compiled.
+    /// `false` for depth materials themselves and built-in materials.
+    allow_depth_material: bool,
 }
 
 impl Material {
@@ -68,34 +107,263 @@ impl Material {
             vertex_shader: vert.to_owned(),
             fragment_shader: frag.to_owned(),
             attribute_locations: HashMap::new(),
+            expected_attributes: Vec::new(),
             shared_uniforms: Default::default(),
             id: id.to_owned(),
             global_uniform_locations: GlobalUniformLocations::new(),
             light_configuration: Default::default(),
             lookup_done: false,
+            program_version: 0,
+            failed_light_configuration: None,
+            precision: None,
+            flip_backface_normals: false,
+            vertex_ao: false,
+            float_defines: Vec::new(),
+            needs_recompilation: false,
+            stencil: None,
+            depth_material: None,
+            allow_depth_material: true,
         }
     }
 
+    /// Creates the built-in magenta material used in place of materials whose shaders
+    /// failed to compile, so that broken meshes are visible instead of disappearing.
+    pub fn new_error_material() -> Material {
+        let mut material = Material::new(
+            crate::utils::constants::ERROR_VERTEX_SHADER,
+            crate::utils::constants::ERROR_FRAGMENT_SHADER,
+            crate::utils::constants::ERROR_MATERIAL_ID,
+        );
+        material.allow_depth_material = false;
+        material
+    }
+
+    /// Creates the built-in flat material used to visualize overdraw.  
+    /// See `Renderer::set_debug_overdraw`.
+    pub fn new_overdraw_material() -> Material {
+        let mut material = Material::new(
+            crate::utils::constants::ERROR_VERTEX_SHADER,
+            crate::utils::constants::OVERDRAW_FRAGMENT_SHADER,
+            crate::utils::constants::OVERDRAW_MATERIAL_ID,
+        );
+        material.allow_depth_material = false;
+        material
+    }
+
+    /// Compiles and links the shaders for the given light configuration.  
+    /// If compilation fails, the previously linked program (if any) is kept so that
+    /// the material keeps rendering its last working version.  
+    /// On success, the previous program is deleted and every location looked up in it,
+    /// including those of `MaterialInstance`s, is looked up again before the next draw.
     pub fn compile(
         &mut self,
         context: &WebGlRenderingContext,
         light_config: &LightConfiguration,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
+        match self.compile_program(context, light_config) {
+            Ok(program) => {
+                self.expected_attributes = read_active_attributes(context, &program);
+                if let Some(previous_program) = self.program.replace(program) {
+                    context.delete_program(Some(&previous_program));
+                }
+                self.invalidate_locations();
+                self.compile_depth_material(context, light_config);
+                self.failed_light_configuration = None;
+                self.needs_recompilation = false;
+                Ok(())
+            }
+            Err(message) => {
+                self.record_compile_failure(light_config);
+                Err(message)
+            }
+        }
+    }
+
+    /// Keeps the current program after a failed compilation, and stops retrying
+    /// until the light configuration or a compile option changes.
+    fn record_compile_failure(&mut self, light_config: &LightConfiguration) {
+        self.failed_light_configuration = Some(light_config.clone());
+    }
+
+    pub fn should_compile(&self, light_config: &LightConfiguration) -> bool {
+        if self.failed_light_configuration.as_ref() == Some(light_config) {
+            return false;
+        }
+        self.program == None
+            || self.needs_recompilation
+            || (self.lit && light_config != &self.light_configuration)
+    }
+
+    /// Replaces the depth material with one sharing this material's vertex shader, if the
+    /// vertex shader only reads the renderer's own uniforms and the fragment shader never
+    /// discards fragments. Otherwise, the depth prepass runs this material's own shaders.
+    fn compile_depth_material(
+        &mut self,
+        context: &WebGlRenderingContext,
+        light_config: &LightConfiguration,
+    ) {
+        if let Some(previous_material) = self.depth_material.take() {
+            if let Some(program) = previous_material.borrow().get_program() {
+                context.delete_program(Some(program));
+            }
+        }
+        if !self.allow_depth_material || self.fragment_shader.contains("discard") {
+            return;
+        }
+        let mut depth_material = Material::new(
+            &self.resolve_vertex_shader(light_config),
+            crate::utils::constants::DEPTH_FRAGMENT_SHADER,
+            &format!(
+                "{}{}",
+                self.id,
+                crate::utils::constants::DEPTH_MATERIAL_SUFFIX
+            ),
+        );
+        depth_material.allow_depth_material = false;
+        if depth_material.compile(context, light_config).is_err() {
+            return;
+        }
+        let program = depth_material.program.as_ref().unwrap();
+        let uses_own_uniforms = read_active_uniform_names(context, program)
+            .iter()
+            .any(|name| !is_renderer_uniform(name));
+        if uses_own_uniforms {
+            context.delete_program(Some(program));
+            return;
+        }
+        depth_material.lookup_locations(context, light_config);
+        self.depth_material = Some(Rc::new(RefCell::new(depth_material)));
+    }
+
+    /// Returns the material drawing this material's meshes during the depth prepass, if any.  
+    /// It shares this material's resolved vertex shader, where `gl_Position` is declared
+    /// `invariant` so that depths match exactly in the `EQUAL` depth test of the color pass,
+    /// with a fragment shader doing nothing. It only exists
+    /// when the vertex shader reads no material or instance uniforms, which the depth
+    /// material does not have, and the fragment shader does not `discard` (alpha test).
+    pub fn get_depth_material(&self) -> Option<Rc<RefCell<Material>>> {
+        self.depth_material.clone()
+    }
+
+    /// Forgets every location looked up in the previous program: global and shared uniforms,
+    /// attributes, and those of the `MaterialInstance`s using this material.
+    fn invalidate_locations(&mut self) {
+        self.global_uniform_locations = GlobalUniformLocations::new();
+        self.attribute_locations.clear();
+        for (_, uniform) in &mut self.shared_uniforms {
+            uniform.reset_location();
+        }
         self.lookup_done = false;
-        let vertex_text = Material::replace_light_constants(&self.vertex_shader, light_config);
-        let fragment_text = Material::replace_light_constants(&self.fragment_shader, light_config);
+        self.program_version = self.program_version.wrapping_add(1);
+    }
+
+    fn compile_program(
+        &self,
+        context: &WebGlRenderingContext,
+        light_config: &LightConfiguration,
+    ) -> Result<WebGlProgram, Error> {
+        let vertex_text = self.resolve_vertex_shader(light_config);
+        let fragment_text = self.resolve_fragment_shader(light_config);
         let vertex = compile_shader(context, WebGlRenderingContext::VERTEX_SHADER, &vertex_text)?;
         let fragment = compile_shader(
             context,
             WebGlRenderingContext::FRAGMENT_SHADER,
             &fragment_text,
         )?;
-        self.program = Some(link_program(context, &vertex, &fragment)?);
-        Ok(())
+        link_program(context, &vertex, &fragment)
     }
 
-    pub fn should_compile(&self, light_config: &LightConfiguration) -> bool {
-        self.program == None || (self.lit && light_config != &self.light_configuration)
+    /// Returns the vertex shader source compiled for `light_config`, with `gl_Position` made
+    /// invariant and defines injected.
+    fn resolve_vertex_shader(&self, light_config: &LightConfiguration) -> String {
+        let mut vertex_text = Material::replace_light_constants(&self.vertex_shader, light_config);
+        vertex_text = Material::make_position_invariant(&vertex_text);
+        if self.vertex_ao {
+            vertex_text =
+                Material::inject_define(&vertex_text, crate::utils::constants::USE_AO_DEFINE);
+        }
+        for (name, value) in &self.float_defines {
+            vertex_text = Material::inject_float_define(&vertex_text, name, *value);
+        }
+        vertex_text
+    }
+
+    /// Returns the fragment shader source compiled for `light_config`, with its precision
+    /// applied and defines injected.
+    fn resolve_fragment_shader(&self, light_config: &LightConfiguration) -> String {
+        let mut fragment_text =
+            Material::replace_light_constants(&self.fragment_shader, light_config);
+        if let Some(precision) = self.precision {
+            fragment_text = self.apply_precision(&fragment_text, precision);
+        }
+        if self.flip_backface_normals {
+            fragment_text = Material::inject_define(
+                &fragment_text,
+                crate::utils::constants::FLIP_BACKFACE_NORMALS_DEFINE,
+            );
+        }
+        if self.vertex_ao {
+            fragment_text =
+                Material::inject_define(&fragment_text, crate::utils::constants::USE_AO_DEFINE);
+        }
+        for (name, value) in &self.float_defines {
+            fragment_text = Material::inject_float_define(&fragment_text, name, *value);
+        }
+        fragment_text
+    }
+
+    /// Forces the float precision of the fragment shader, overriding its own
+    /// `precision ... float;` declaration. The material is recompiled before its next use.
+    pub fn set_precision(&mut self, precision: Precision) {
+        if self.precision != Some(precision) {
+            self.precision = Some(precision);
+            self.needs_recompilation = true;
+            self.failed_light_configuration = None;
+        }
+    }
+
+    /// Flips the normals of back faces so that double-sided surfaces are lit correctly on
+    /// both sides. Defines `FLIP_BACKFACE_NORMALS` in the fragment shader, which must
+    /// handle it using `gl_FrontFacing`. The material is recompiled before its next use.
+    pub fn set_flip_backface_normals(&mut self, flip_backface_normals: bool) {
+        if self.flip_backface_normals != flip_backface_normals {
+            self.flip_backface_normals = flip_backface_normals;
+            self.needs_recompilation = true;
+            self.failed_light_configuration = None;
+        }
+    }
+
+    /// Reads baked per-vertex ambient occlusion from the `a_ao` buffer, by defining `USE_AO`
+    /// in both shaders. Meshes without an `a_ao` buffer are drawn unoccluded.  
+    /// Enabled automatically for materials used by a mesh with an `a_ao` buffer.
+    /// The material is recompiled before its next use.
+    pub fn set_vertex_ao(&mut self, vertex_ao: bool) {
+        if self.vertex_ao != vertex_ao {
+            self.vertex_ao = vertex_ao;
+            self.needs_recompilation = true;
+            self.failed_light_configuration = None;
+        }
+    }
+
+    /// Getter for `vertex_ao`
+    pub fn uses_vertex_ao(&self) -> bool {
+        self.vertex_ao
+    }
+
+    /// Defines the `name` float constant in both shaders, for values such as ranges or
+    /// cutoffs that are fixed for the material. The material is recompiled before its next use.
+    pub fn set_float_define(&mut self, name: &str, value: f32) {
+        match self
+            .float_defines
+            .iter_mut()
+            .find(|(define, _)| define == name)
+        {
+            Some((_, current)) if *current == value => return,
+            Some((_, current)) => *current = value,
+            None => self.float_defines.push((name.to_owned(), value)),
+        }
+        self.needs_recompilation = true;
+        self.failed_light_configuration = None;
     }
 
     /// Used by buffers to register new attributes to a material.
@@ -112,6 +380,12 @@ impl Material {
         }
     }
 
+    /// Returns the active attributes of the linked program, with their type in the shader.  
+    /// Empty until the material has been compiled.
+    pub fn get_expected_attributes(&self) -> &[(String, ShaderDataType)] {
+        &self.expected_attributes
+    }
+
     /// Returns a previously computed attribute location if available.
     pub fn get_attribute_location(&self, name: &str) -> Option<i32> {
         if let Some(loc_option) = self.attribute_locations.get(name) {
@@ -139,6 +413,21 @@ impl Material {
         self.lookup_done = true;
     }
 
+    /// Applies `stencil` around the draws of this material, e.g. to mark the region of a portal.
+    pub fn set_stencil(&mut self, stencil: StencilConfig) {
+        self.stencil = Some(stencil);
+    }
+
+    /// Draws this material without stencil test again.
+    pub fn disable_stencil(&mut self) {
+        self.stencil = None;
+    }
+
+    /// Getter for `stencil`
+    pub fn get_stencil(&self) -> Option<StencilConfig> {
+        self.stencil
+    }
+
     /// `self.opaque` setter. Use if your `Material` is semi-transparent.
     pub fn set_transparent(&mut self, transparent: bool) -> () {
         self.opaque = !transparent;
@@ -172,14 +461,37 @@ impl Material {
     }
 
     /// Updates the context with all of this material's uniform.  
-    /// Should be called before rendering objects using this material.
-    pub fn set_uniforms_to_context(&self, context: &WebGlRenderingContext) -> Result<(), String> {
+    /// Should be called before rendering objects using this material.  
+    /// Every uniform is uploaded, but the first error met is returned.
+    pub fn set_uniforms_to_context(&self, context: &WebGlRenderingContext) -> Result<(), Error> {
+        let mut result = Ok(());
         for (_, uniform) in &self.shared_uniforms {
-            uniform.set_to_context(context).unwrap_or_else(|message| {
-                console_warn(&message[..]);
-            });
+            result = result.and(uniform.set_to_context(context));
+        }
+        result
+    }
+
+    /// Returns the shared `Uniform`s whose name is in `names`.
+    pub fn get_shared_uniforms_named<'a>(&'a self, names: &'a [String]) -> Vec<&'a Uniform> {
+        self.shared_uniforms
+            .iter()
+            .filter(|(name, _)| names.contains(name))
+            .map(|(_, uniform)| uniform)
+            .collect()
+    }
+
+    /// Updates the context with the shared `Uniform`s whose name is in `names`.  
+    /// Used to restore the values overridden by a `MaterialInstance` before drawing another one.
+    pub fn set_uniforms_named_to_context(
+        &self,
+        context: &WebGlRenderingContext,
+        names: &[String],
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        for uniform in self.get_shared_uniforms_named(names) {
+            result = result.and(uniform.set_to_context(context));
         }
-        Ok(())
+        result
     }
 
     /// Returns a reference to this `Material`'s underlying `WebGlProgram`.
@@ -193,7 +505,7 @@ impl Material {
     }
 
     /// Get a Hashmap of the Texture uniforms and their texture indexes
-    pub fn get_texture_indexes(&self) -> Result<HashMap<String, u32>, String> {
+    pub fn get_texture_indexes(&self) -> Result<HashMap<String, u32>, Error> {
         let mut result = HashMap::new();
         for uniform_data in &self.shared_uniforms {
             match uniform_data.1.get_texture_index() {
@@ -208,17 +520,116 @@ impl Material {
         Ok(result)
     }
 
+    /// Replaces the float precision declaration of `shader` with `precision`, or inserts one
+    /// after the `#version` directive (or at the top) if there is none.
+    fn apply_precision(&self, shader: &str, precision: Precision) -> String {
+        let declaration = format!("precision {} float;", precision.get_qualifier());
+        let mut lines: Vec<String> = Vec::new();
+        let mut declared = false;
+        for line in shader.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("precision ") && trimmed.ends_with(" float;") {
+                if trimmed != declaration {
+                    console_warn(&format!(
+                        "Material {} declares `{}`, which is overridden by `{}`.",
+                        self.id, trimmed, declaration
+                    ));
+                }
+                if !declared {
+                    lines.push(declaration.clone());
+                    declared = true;
+                }
+            } else {
+                lines.push(line.to_owned());
+            }
+        }
+        if !declared {
+            let position = match lines.first() {
+                Some(first) if first.trim().starts_with("#version") => 1,
+                _ => 0,
+            };
+            lines.insert(position, declaration);
+        }
+        lines.join("\n")
+    }
+
+    /// Defines the `name` preprocessor symbol at the top of `shader`, after the `#version`
+    /// directive if there is one.
+    fn inject_define(shader: &str, name: &str) -> String {
+        Material::inject_line(shader, &format!("#define {}", name))
+    }
+
+    /// Inserts `line` at the top of `shader`, after the `#version` directive if there is one.
+    fn inject_line(shader: &str, line: &str) -> String {
+        if shader.trim_start().starts_with("#version") {
+            match shader.find('\n') {
+                Some(end) => format!("{}{}\n{}", &shader[..=end], line, &shader[end + 1..]),
+                None => format!("{}\n{}\n", shader, line),
+            }
+        } else {
+            format!("{}\n{}", line, shader)
+        }
+    }
+
+    /// Declares `gl_Position` invariant in `shader` unless it already is, so that programs
+    /// sharing the vertex shader compute identical depths.
+    fn make_position_invariant(shader: &str) -> String {
+        if shader.contains(crate::utils::constants::INVARIANT_POSITION_DECLARATION) {
+            shader.to_owned()
+        } else {
+            Material::inject_line(
+                shader,
+                crate::utils::constants::INVARIANT_POSITION_DECLARATION,
+            )
+        }
+    }
+
+    /// Defines the `name` preprocessor symbol as a GLSL float literal for `value`.
+    fn inject_float_define(shader: &str, name: &str, value: f32) -> String {
+        Material::inject_define(
+            shader,
+            &format!("{} {}", name, crate::utils::format_glsl_float(value)),
+        )
+    }
+
     fn replace_light_constants(shader: &str, light_config: &LightConfiguration) -> String {
         shader
             .replace("#define NUM_DIR_LIGHTS", "//")
             .replace("#define NUM_POINT_LIGHTS", "//")
             .replace("#define NUM_SPOT_LIGHTS", "//")
+            .replace("#define NUM_TUBE_LIGHTS", "//")
             .replace("NUM_DIR_LIGHTS", &format!("{}", light_config.directional))
             .replace("NUM_POINT_LIGHTS", &format!("{}", light_config.point))
             .replace("NUM_SPOT_LIGHTS", &format!("{}", light_config.spot))
+            .replace("NUM_TUBE_LIGHTS", &format!("{}", light_config.tube))
     }
 }
 
+/// ## Precision
+///
+/// Float precision qualifier for fragment shaders.
+/// Lower precisions are faster on mobile GPUs but may cause banding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    High,
+    Medium,
+    Low,
+}
+
+impl Precision {
+    /// GLSL qualifier for this precision
+    pub fn get_qualifier(&self) -> &'static str {
+        match self {
+            Precision::High => "highp",
+            Precision::Medium => "mediump",
+            Precision::Low => "lowp",
+        }
+    }
+}
+
+/// Callback notified of uniform changes on a `MaterialInstance`, with the uniform's name and new value.
+pub type UniformObserver = Box<dyn FnMut(&str, &Uniform)>;
+
 /// ## `MaterialInstance`
 ///
 /// A Mesh-specific material instance. While `Material` is meant to be shared,
@@ -236,8 +647,16 @@ pub struct MaterialInstance {
     /// Unique ID for this material instance
     id: String,
 
-    /// Location lookup state to avoid doing it each frame once it has been done once.
-    lookup_done: bool,
+    /// `program_version` of the parent material when locations were last looked up,
+    /// to avoid doing it each frame while the parent's program stays the same.
+    lookup_program_version: Option<u32>,
+
+    /// Callback invoked with the name and new value of each uniform added or replaced.
+    uniform_observer: Option<UniformObserver>,
+
+    /// Sorted registry indices of the textures bound by this instance's own uniforms,
+    /// used to draw instances sharing textures one after the other.
+    texture_set: Vec<usize>,
 }
 
 impl MaterialInstance {
@@ -247,27 +666,42 @@ impl MaterialInstance {
             parent_material: parent_material,
             uniforms: Default::default(),
             id: id.to_owned(),
-            lookup_done: false,
+            lookup_program_version: None,
+            uniform_observer: None,
+            texture_set: Vec::new(),
         }
     }
 
+    /// Registers a callback invoked with the name and new value of every uniform
+    /// added to or replaced in this `MaterialInstance`. Replaces any previous callback.
+    pub fn on_uniform_changed(&mut self, observer: UniformObserver) {
+        self.uniform_observer = Some(observer);
+    }
+
     /// Lookup locations for this `MaterialInstance`.  
     /// If locations are missing from the parent material, they will be computed
-    /// automatically.
+    /// automatically. Locations are looked up again after the parent is recompiled.
     pub fn lookup_locations(
         &mut self,
         context: &WebGlRenderingContext,
         light_config: &LightConfiguration,
     ) -> () {
-        if self.lookup_done {
+        let mut parent_mat = self.parent_material.borrow_mut();
+        if self.lookup_program_version == Some(parent_mat.program_version) {
             return;
         }
-        let mut parent_mat = self.parent_material.borrow_mut();
         parent_mat.lookup_locations(context, light_config);
         for (_, uniform) in &mut self.uniforms {
+            uniform.reset_location();
             uniform.lookup_location(context, parent_mat.get_program());
         }
-        self.lookup_done = true;
+        self.lookup_program_version = Some(parent_mat.program_version);
+    }
+
+    /// Returns `true` if the locations of this `MaterialInstance` must be looked up before
+    /// its next draw, because they never were or because its parent was recompiled since.
+    pub fn needs_lookup(&self) -> bool {
+        self.lookup_program_version != Some(self.parent_material.borrow().program_version)
     }
 
     /// Adds a new set of `Uniform`s to this `MaterialInstance`, as a batch.  
@@ -279,6 +713,19 @@ impl MaterialInstance {
         }
     }
 
+    /// Sets the registry indices of the textures bound by this instance's own uniforms.  
+    /// Set when loading a `MaterialInstanceFile`; used as a draw sort key.
+    pub fn set_texture_set(&mut self, mut texture_set: Vec<usize>) {
+        texture_set.sort();
+        texture_set.dedup();
+        self.texture_set = texture_set;
+    }
+
+    /// Getter for texture_set
+    pub fn get_texture_set(&self) -> &[usize] {
+        &self.texture_set
+    }
+
     /// Getter for the parent's `Material` transparency setting.
     pub fn is_transparent(&self) -> bool {
         self.parent_material.borrow().is_transparent()
@@ -286,14 +733,30 @@ impl MaterialInstance {
 
     /// Adds or update a mesh-specific `Uniform`.
     pub fn set_uniform(&mut self, uniform_to_set: Uniform) {
-        for mut uniform in &mut self.uniforms {
-            if &uniform.0 == &uniform_to_set.name {
-                uniform.1 = uniform_to_set;
-                return;
+        let position = self
+            .uniforms
+            .iter()
+            .position(|uniform| &uniform.0 == &uniform_to_set.name);
+        let index = match position {
+            Some(index) => {
+                self.uniforms[index].1 = uniform_to_set;
+                index
+            }
+            None => {
+                self.uniforms
+                    .push((uniform_to_set.name.clone(), uniform_to_set));
+                self.uniforms.len() - 1
             }
+        };
+        if let Some(observer) = &mut self.uniform_observer {
+            let (name, uniform) = &self.uniforms[index];
+            observer(name, uniform);
         }
-        self.uniforms
-            .push((uniform_to_set.name.clone(), uniform_to_set));
+    }
+
+    /// Returns the names of this `MaterialInstance`'s own `Uniform`s.
+    pub fn get_uniform_names(&self) -> Vec<String> {
+        self.uniforms.iter().map(|(name, _)| name.clone()).collect()
     }
 
     /// Updates a global `Uniform` from this `MaterialInstance`'s parent `Material`.
@@ -320,14 +783,14 @@ impl MaterialInstance {
     /// Updates the context with all of this material's uniform, not including the parent
     /// `Material`'s `Uniform`s.   
     /// Should be called before rendering the Mesh using this `MaterialInstance`.  
-    /// ⚠️ The parent's `Uniforms` should be set before that step.
-    pub fn set_uniforms_to_context(&self, context: &WebGlRenderingContext) -> Result<(), String> {
+    /// ⚠️ The parent's `Uniforms` should be set before that step.  
+    /// Every uniform is uploaded, but the first error met is returned.
+    pub fn set_uniforms_to_context(&self, context: &WebGlRenderingContext) -> Result<(), Error> {
+        let mut result = Ok(());
         for (_, uniform) in &self.uniforms {
-            uniform.set_to_context(context).unwrap_or_else(|message| {
-                console_warn(&message[..]);
-            });
+            result = result.and(uniform.set_to_context(context));
         }
-        Ok(())
+        result
     }
 }
 
@@ -336,10 +799,10 @@ fn compile_shader(
     context: &WebGlRenderingContext,
     shader_type: u32,
     source: &str,
-) -> Result<WebGlShader, String> {
+) -> Result<WebGlShader, Error> {
     let shader = context
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| Error::ShaderCompile(String::from("Unable to create shader object")))?;
     context.shader_source(&shader, source);
     context.compile_shader(&shader);
 
@@ -350,23 +813,83 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        let err = Err(context
-            .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")));
+        let err = Err(Error::ShaderCompile(
+            context
+                .get_shader_info_log(&shader)
+                .unwrap_or_else(|| String::from("Unknown error creating shader")),
+        ));
         context.delete_shader(Some(&shader));
         err
     }
 }
 
+/// Enumerates the active attributes of a linked program.  
+/// Attributes of a type with no `ShaderDataType` equivalent are skipped.
+fn read_active_attributes(
+    context: &WebGlRenderingContext,
+    program: &WebGlProgram,
+) -> Vec<(String, ShaderDataType)> {
+    let count = context
+        .get_program_parameter(program, WebGlRenderingContext::ACTIVE_ATTRIBUTES)
+        .as_f64()
+        .unwrap_or(0.) as u32;
+    let mut attributes = Vec::new();
+    for index in 0..count {
+        if let Some(info) = context.get_active_attrib(program, index) {
+            let data_type = match info.type_() {
+                WebGlRenderingContext::FLOAT => ShaderDataType::Single,
+                WebGlRenderingContext::FLOAT_VEC2 => ShaderDataType::Vector2,
+                WebGlRenderingContext::FLOAT_VEC3 => ShaderDataType::Vector3,
+                WebGlRenderingContext::FLOAT_VEC4 => ShaderDataType::Vector4,
+                WebGlRenderingContext::FLOAT_MAT2 => ShaderDataType::Matrix2,
+                WebGlRenderingContext::FLOAT_MAT3 => ShaderDataType::Matrix3,
+                WebGlRenderingContext::FLOAT_MAT4 => ShaderDataType::Matrix4,
+                _ => continue,
+            };
+            attributes.push((info.name(), data_type));
+        }
+    }
+    attributes
+}
+
+/// Enumerates the names of the active uniforms of a linked program.  
+/// Array uniforms are named after their first element, e.g. `u_lights[0]`.
+fn read_active_uniform_names(
+    context: &WebGlRenderingContext,
+    program: &WebGlProgram,
+) -> Vec<String> {
+    let count = context
+        .get_program_parameter(program, WebGlRenderingContext::ACTIVE_UNIFORMS)
+        .as_f64()
+        .unwrap_or(0.) as u32;
+    (0..count)
+        .filter_map(|index| context.get_active_uniform(program, index))
+        .map(|info| info.name())
+        .collect()
+}
+
+/// Returns `true` if `name` is a uniform set by the renderer itself for every material.
+fn is_renderer_uniform(name: &str) -> bool {
+    [
+        crate::utils::constants::VIEW_MATRIX_NAME,
+        crate::utils::constants::CAMERA_POSITION_NAME,
+        crate::utils::constants::PROJECTION_MATRIX_NAME,
+        crate::utils::constants::WORLD_TRANSFORM_NAME,
+        crate::utils::constants::TIME_NAME,
+        crate::utils::constants::DELTA_TIME_NAME,
+    ]
+    .contains(&name)
+}
+
 /// Boilerplate program linking function taken from the `wasm-bindgen` WebGL example.
 fn link_program(
     context: &WebGlRenderingContext,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
+) -> Result<WebGlProgram, Error> {
     let program = context
         .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| Error::ShaderLink(String::from("Unable to create program object")))?;
 
     context.attach_shader(&program, vert_shader);
     context.attach_shader(&program, frag_shader);
@@ -379,10 +902,290 @@ fn link_program(
     {
         Ok(program)
     } else {
-        let err = Err(context
-            .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")));
+        let err = Err(Error::ShaderLink(
+            context
+                .get_program_info_log(&program)
+                .unwrap_or_else(|| String::from("Unknown error creating program object")),
+        ));
         context.delete_program(Some(&program));
         err
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::uniform::UniformValue;
+    use nalgebra::Vector3;
+    use wasm_bindgen::JsCast;
+
+    const VERTEX_SHADER: &str = "attribute vec4 a_position;
+void main() {
+    gl_Position = a_position;
+}";
+
+    const FRAGMENT_SHADER: &str = "precision mediump float;
+void main() {
+    gl_FragColor = vec4(1.0);
+}";
+
+    fn new_material(fragment_shader: &str) -> Material {
+        Material::new(VERTEX_SHADER, fragment_shader, "material")
+    }
+
+    #[test]
+    fn precision_replaces_the_existing_declaration() {
+        let material = new_material(FRAGMENT_SHADER);
+        let shader = material.apply_precision(FRAGMENT_SHADER, Precision::High);
+        assert!(shader.starts_with("precision highp float;\n"));
+        assert!(!shader.contains("mediump"));
+        assert_eq!(shader.matches("precision").count(), 1);
+    }
+
+    #[test]
+    fn precision_is_inserted_after_the_version_directive() {
+        let material = new_material(FRAGMENT_SHADER);
+        let shader = material.apply_precision(
+            "#version 100\nvoid main() {\n    gl_FragColor = vec4(1.0);\n}",
+            Precision::Low,
+        );
+        let lines: Vec<&str> = shader.lines().collect();
+        assert_eq!(lines[0], "#version 100");
+        assert_eq!(lines[1], "precision lowp float;");
+    }
+
+    #[test]
+    fn precision_is_inserted_at_the_top_without_declaration() {
+        let material = new_material(FRAGMENT_SHADER);
+        let shader = material.apply_precision("void main() {}", Precision::Medium);
+        assert_eq!(shader, "precision mediump float;\nvoid main() {}");
+    }
+
+    #[test]
+    fn requested_precision_appears_in_the_resolved_fragment_shader() {
+        let mut material = new_material(FRAGMENT_SHADER);
+        material.set_precision(Precision::High);
+        let shader = material.resolve_fragment_shader(&LightConfiguration::default());
+        assert!(shader.contains("precision highp float;"));
+        assert!(!shader.contains("precision mediump float;"));
+    }
+
+    #[test]
+    fn resolved_vertex_shaders_declare_an_invariant_position() {
+        let material = new_material(FRAGMENT_SHADER);
+        let shader = material.resolve_vertex_shader(&LightConfiguration::default());
+        assert!(shader.starts_with("invariant gl_Position;\n"));
+        let declared = Material::make_position_invariant(&shader);
+        assert_eq!(declared.matches("invariant gl_Position;").count(), 1);
+    }
+
+    #[test]
+    fn invariant_position_follows_the_version_directive() {
+        let shader = Material::make_position_invariant("#version 100\nvoid main() {}");
+        assert_eq!(
+            shader,
+            "#version 100\ninvariant gl_Position;\nvoid main() {}"
+        );
+    }
+
+    #[test]
+    fn float_defines_are_injected_as_glsl_floats() {
+        let mut material = new_material(FRAGMENT_SHADER);
+        material.set_float_define("ALPHA_CUTOFF", 1.0);
+        material.set_float_define("FADE_RANGE", 100.0);
+        material.set_float_define("ALPHA_CUTOFF", 0.5);
+        let config = LightConfiguration::default();
+        for shader in [
+            material.resolve_vertex_shader(&config),
+            material.resolve_fragment_shader(&config),
+        ]
+        .iter()
+        {
+            assert!(shader.contains("#define ALPHA_CUTOFF 0.5\n"));
+            assert!(shader.contains("#define FADE_RANGE 100.0\n"));
+            assert_eq!(shader.matches("ALPHA_CUTOFF").count(), 1);
+        }
+    }
+
+    #[test]
+    fn precision_is_left_untouched_by_default() {
+        let material = new_material(FRAGMENT_SHADER);
+        let shader = material.resolve_fragment_shader(&LightConfiguration::default());
+        assert_eq!(shader, FRAGMENT_SHADER);
+    }
+
+    #[test]
+    fn define_is_injected_at_the_top() {
+        let shader = Material::inject_define(FRAGMENT_SHADER, "FLIP_BACKFACE_NORMALS");
+        assert_eq!(
+            shader,
+            format!("#define FLIP_BACKFACE_NORMALS\n{}", FRAGMENT_SHADER)
+        );
+    }
+
+    #[test]
+    fn define_is_injected_after_the_version_directive() {
+        let shader = Material::inject_define("#version 100\nvoid main() {}", "USE_AO");
+        assert_eq!(shader, "#version 100\n#define USE_AO\nvoid main() {}");
+    }
+
+    #[test]
+    fn flip_backface_normals_flag_injects_its_define() {
+        let mut material = new_material(FRAGMENT_SHADER);
+        let light_config = LightConfiguration::default();
+        assert!(!material
+            .resolve_fragment_shader(&light_config)
+            .contains(crate::utils::constants::FLIP_BACKFACE_NORMALS_DEFINE));
+        material.set_flip_backface_normals(true);
+        assert!(material
+            .resolve_fragment_shader(&light_config)
+            .contains("#define FLIP_BACKFACE_NORMALS"));
+    }
+
+    #[test]
+    fn only_renderer_uniforms_allow_a_depth_material() {
+        assert!(is_renderer_uniform(
+            crate::utils::constants::WORLD_TRANSFORM_NAME
+        ));
+        assert!(is_renderer_uniform(crate::utils::constants::TIME_NAME));
+        assert!(!is_renderer_uniform("u_wave_amplitude"));
+        assert!(new_material(FRAGMENT_SHADER).get_depth_material().is_none());
+    }
+
+    #[test]
+    fn recompiling_twice_invalidates_locations_each_time() {
+        let material = Rc::new(RefCell::new(Material::new(
+            VERTEX_SHADER,
+            "#define NUM_POINT_LIGHTS 0\nuniform Light u_point_lights[NUM_POINT_LIGHTS];",
+            "lit_material",
+        )));
+        let mut instance = MaterialInstance::new(material.clone(), "instance");
+        for point_lights in 1..3 {
+            let light_config = LightConfiguration {
+                point: point_lights,
+                ..Default::default()
+            };
+            assert!(material.borrow().should_compile(&light_config));
+            assert!(material
+                .borrow()
+                .resolve_fragment_shader(&light_config)
+                .contains(&format!("u_point_lights[{}]", point_lights)));
+            {
+                let mut material = material.borrow_mut();
+                material
+                    .attribute_locations
+                    .insert(String::from(crate::utils::constants::VERTEX_BUFFER_NAME), 0);
+                material.lookup_done = true;
+                material.light_configuration = light_config.clone();
+            }
+            instance.lookup_program_version = Some(material.borrow().program_version);
+            assert!(!instance.needs_lookup());
+
+            material.borrow_mut().invalidate_locations();
+
+            let material = material.borrow();
+            assert!(material
+                .get_attribute_location(crate::utils::constants::VERTEX_BUFFER_NAME)
+                .is_none());
+            assert!(!material.lookup_done);
+            assert!(material
+                .global_uniform_locations
+                .point_lights_locations
+                .is_empty());
+            assert!(instance.needs_lookup());
+        }
+    }
+
+    #[test]
+    fn overridden_parent_uniforms_are_restored_between_instances() {
+        let material = Rc::new(RefCell::new(new_material(FRAGMENT_SHADER)));
+        material.borrow_mut().set_uniform(Uniform::new(
+            "u_color",
+            Box::new(Vector3::new(1.0, 1.0, 1.0)),
+        ));
+        material
+            .borrow_mut()
+            .set_uniform(Uniform::new("u_roughness", Box::new(0.5)));
+        let uploaded = Rc::new(RefCell::new(Vec::new()));
+        let mut instance = MaterialInstance::new(material.clone(), "instance");
+        let observed = uploaded.clone();
+        instance.on_uniform_changed(Box::new(move |name, _| {
+            observed.borrow_mut().push(name.to_owned())
+        }));
+        instance.set_uniform(Uniform::new(
+            "u_color",
+            Box::new(Vector3::new(1.0, 0.0, 0.0)),
+        ));
+        instance.set_uniform(Uniform::new("u_tint", Box::new(0.2)));
+
+        let names = instance.get_uniform_names();
+        assert_eq!(names, *uploaded.borrow());
+        let material = material.borrow();
+        let restored = material.get_shared_uniforms_named(&names);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].name, "u_color");
+    }
+
+    #[test]
+    fn uniform_observer_receives_added_and_replaced_uniforms() {
+        let value_address =
+            |value: &dyn UniformValue| value as *const dyn UniformValue as *const u8;
+        let material = Rc::new(RefCell::new(new_material(FRAGMENT_SHADER)));
+        let mut instance = MaterialInstance::new(material, "instance");
+        let observed = Rc::new(RefCell::new(Vec::new()));
+        let recorder = observed.clone();
+        instance.on_uniform_changed(Box::new(move |name, uniform| {
+            recorder
+                .borrow_mut()
+                .push((name.to_owned(), value_address(&*uniform.value)))
+        }));
+        let added: Box<dyn UniformValue> = Box::new(0.25);
+        let added_address = value_address(&*added);
+        instance.set_uniform(Uniform::new("u_opacity", added));
+        let replaced: Box<dyn UniformValue> = Box::new(0.75);
+        let replaced_address = value_address(&*replaced);
+        instance.set_uniform(Uniform::new("u_opacity", replaced));
+
+        assert_eq!(
+            *observed.borrow(),
+            [
+                (String::from("u_opacity"), added_address),
+                (String::from("u_opacity"), replaced_address)
+            ]
+        );
+        assert_eq!(instance.get_uniform_names(), ["u_opacity"]);
+    }
+
+    #[test]
+    fn failed_recompilation_keeps_the_previous_program() {
+        let mut material = new_material(FRAGMENT_SHADER);
+        let light_config = LightConfiguration::default();
+        material.program = Some(wasm_bindgen::JsValue::NULL.unchecked_into());
+        material.set_precision(Precision::High);
+        assert!(material.should_compile(&light_config));
+
+        material.record_compile_failure(&light_config);
+        assert!(material.get_program().is_some());
+        assert!(!material.should_compile(&light_config));
+
+        material.set_precision(Precision::Low);
+        assert!(material.should_compile(&light_config));
+    }
+
+    #[test]
+    fn vertex_ao_defines_use_ao_in_both_shaders() {
+        let mut material = new_material(FRAGMENT_SHADER);
+        let light_config = LightConfiguration::default();
+        let define = format!("#define {}", crate::utils::constants::USE_AO_DEFINE);
+        assert!(!material
+            .resolve_vertex_shader(&light_config)
+            .contains(&define));
+        material.set_vertex_ao(true);
+        assert!(material
+            .resolve_vertex_shader(&light_config)
+            .contains(&define));
+        assert!(material
+            .resolve_fragment_shader(&light_config)
+            .contains(&define));
+    }
+}
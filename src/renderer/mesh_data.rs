@@ -1,11 +1,20 @@
 //! Representation of mesh data with its vertices and all buffer data.
 
-use crate::renderer::buffer::Buffer;
+use crate::error::Error;
+use crate::renderer::buffer::{Buffer, BufferUsage};
 use crate::renderer::Material;
+use crate::utils::console_warn;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::vec::Vec;
 use web_sys::WebGlRenderingContext;
+use wtvr3d_file::ShaderDataType;
+
+/// 2D clip-space positions of the fullscreen triangle's vertices.
+const FULLSCREEN_TRIANGLE_POSITIONS: [f32; 6] = [-1., -1., 3., -1., -1., 3.];
+
+/// Texture coordinates of the fullscreen triangle's vertices, spanning `[0, 1]` over the screen.
+const FULLSCREEN_TRIANGLE_TEX_COORDINATES: [f32; 6] = [0., 0., 2., 0., 0., 2.];
 
 /// Mesh data as the union of its `Buffers` and the number of vertices in the mesh
 pub struct MeshData {
@@ -34,6 +43,34 @@ impl MeshData {
         }
     }
 
+    /// Creates a single triangle covering the whole clip space, for fullscreen passes.  
+    /// Its 2D clip-space positions are stored in the `a_position` buffer and its texture
+    /// coordinates, spanning `[0, 1]` over the screen, in the `a_tex_coordinates` buffer.
+    /// Unlike a quad, it has no diagonal seam.
+    pub fn new_fullscreen_triangle(context: &WebGlRenderingContext) -> MeshData {
+        let mut mesh_data = MeshData::new(
+            String::from(crate::utils::constants::FULLSCREEN_TRIANGLE_ID),
+            3,
+        );
+        mesh_data.push_buffer(Buffer::from_f32_data_view(
+            context,
+            crate::utils::constants::VERTEX_BUFFER_NAME,
+            ShaderDataType::Vector2,
+            &FULLSCREEN_TRIANGLE_POSITIONS,
+            Some(&[0, 1, 2]),
+            BufferUsage::Static,
+        ));
+        mesh_data.push_buffer(Buffer::from_f32_data_view(
+            context,
+            crate::utils::constants::UV_BUFFER_NAME,
+            ShaderDataType::Vector2,
+            &FULLSCREEN_TRIANGLE_TEX_COORDINATES,
+            None,
+            BufferUsage::Static,
+        ));
+        mesh_data
+    }
+
     /// Add a buffer to this `MeshData`
     pub fn push_buffer(&mut self, buffer: Buffer) -> () {
         self.buffers.push(buffer);
@@ -53,6 +90,83 @@ impl MeshData {
         None
     }
 
+    /// Removes the named attribute buffers, e.g. those a material does not use, and deletes
+    /// their GPU data. The vertex position buffer cannot be removed.
+    pub fn strip_attributes(&mut self, context: &WebGlRenderingContext, names: &[&str]) {
+        self.remove_buffers(context, |name| names.contains(&name));
+    }
+
+    /// Removes every attribute buffer not listed in `names` and deletes their GPU data.
+    /// The vertex position buffer is always kept.
+    pub fn retain_attributes(&mut self, context: &WebGlRenderingContext, names: &[&str]) {
+        self.remove_buffers(context, |name| !names.contains(&name));
+    }
+
+    fn remove_buffers<F: Fn(&str) -> bool>(
+        &mut self,
+        context: &WebGlRenderingContext,
+        should_remove: F,
+    ) {
+        let names: Vec<&str> = self
+            .buffers
+            .iter()
+            .map(|buffer| buffer.get_attribute_name())
+            .collect();
+        if names.contains(&crate::utils::constants::VERTEX_BUFFER_NAME)
+            && should_remove(crate::utils::constants::VERTEX_BUFFER_NAME)
+        {
+            console_warn(&format!(
+                "The vertex position buffer of mesh data {} cannot be removed.",
+                self.id
+            ));
+        }
+        let removed: Vec<String> = get_removed_attributes(&names, should_remove)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        self.buffers.retain(|buffer| {
+            if removed
+                .iter()
+                .any(|name| name == buffer.get_attribute_name())
+            {
+                buffer.delete(context);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Replaces the triangle indices of this `MeshData`, stored with its vertex position buffer.  
+    /// The smallest index type able to hold every index is used. See `Buffer::set_indices`.
+    pub fn set_indices(
+        &mut self,
+        context: &WebGlRenderingContext,
+        indices: &[u32],
+    ) -> Result<(), Error> {
+        for buffer in &mut self.buffers {
+            if buffer.get_attribute_name() == crate::utils::constants::VERTEX_BUFFER_NAME {
+                buffer.set_indices(context, indices)?;
+                self.vertex_count = indices.len() as i32;
+                return Ok(());
+            }
+        }
+        Err(Error::Load(format!(
+            "Mesh data {} has no vertex position buffer to attach indices to.",
+            self.id
+        )))
+    }
+
+    /// Returns the type of this `MeshData`'s indices, to be used with `draw_elements`.
+    pub fn get_index_type(&self) -> u32 {
+        for buffer in &self.buffers {
+            if buffer.has_indices() {
+                return buffer.get_index_type();
+            }
+        }
+        WebGlRenderingContext::UNSIGNED_SHORT
+    }
+
     /// Returns the number of vertices for this `MeshData`'s Buffers.
     pub fn get_vertex_count(&self) -> i32 {
         self.vertex_count
@@ -63,6 +177,24 @@ impl MeshData {
         &self.id
     }
 
+    /// Warns about attributes expected by `material`'s shader that this `MeshData` lacks,
+    /// or provides with more components than the shader reads. See `get_attribute_warnings`.
+    fn check_attributes(&self, material: &Material) {
+        let buffer_types: Vec<(&str, &ShaderDataType)> = self
+            .buffers
+            .iter()
+            .map(|buffer| (buffer.get_attribute_name(), buffer.get_data_type()))
+            .collect();
+        for warning in get_attribute_warnings(
+            &self.id,
+            &buffer_types,
+            material.get_id(),
+            material.get_expected_attributes(),
+        ) {
+            console_warn(&warning);
+        }
+    }
+
     /// Function to lookup the locations for this meshdata;
     pub fn lookup_locations(
         &mut self,
@@ -72,6 +204,7 @@ impl MeshData {
         if self.lookup_done {
             return;
         }
+        self.check_attributes(&material.borrow());
         for buffer in &self.buffers {
             material
                 .borrow_mut()
@@ -80,3 +213,113 @@ impl MeshData {
         self.lookup_done = true;
     }
 }
+
+/// Returns the attributes of `names` selected by `should_remove`, except the vertex
+/// position buffer which is always kept.
+fn get_removed_attributes<'a, F: Fn(&str) -> bool>(
+    names: &[&'a str],
+    should_remove: F,
+) -> Vec<&'a str> {
+    names
+        .iter()
+        .filter(|name| **name != crate::utils::constants::VERTEX_BUFFER_NAME && should_remove(name))
+        .cloned()
+        .collect()
+}
+
+/// Returns a warning for each attribute in `expected_attributes` missing from `buffer_types`,
+/// or provided with more components than the shader reads.  
+/// Buffers with fewer components are fine: WebGL fills the missing ones with `(0, 0, 0, 1)`.
+fn get_attribute_warnings(
+    mesh_data_id: &str,
+    buffer_types: &[(&str, &ShaderDataType)],
+    material_id: &str,
+    expected_attributes: &[(String, ShaderDataType)],
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (name, data_type) in expected_attributes {
+        match buffer_types
+            .iter()
+            .find(|(buffer_name, _)| buffer_name == name)
+        {
+            None => warnings.push(format!(
+                "Mesh data {} has no {} buffer, expected by material {}.",
+                mesh_data_id, name, material_id
+            )),
+            Some((_, buffer_type)) if buffer_type.get_size() > data_type.get_size() => {
+                warnings.push(format!(
+                    "Mesh data {} has {} components per vertex in its {} buffer, but material {} only reads {}.",
+                    mesh_data_id,
+                    buffer_type.get_size(),
+                    name,
+                    material_id,
+                    data_type.get_size()
+                ))
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fullscreen_triangle_covers_clip_space() {
+        let vertices: Vec<&[f32]> = FULLSCREEN_TRIANGLE_POSITIONS.chunks(2).collect();
+        assert_eq!(vertices.len(), 3);
+        let edge = |a: &[f32], b: &[f32], (x, y): (f32, f32)| {
+            (b[0] - a[0]) * (y - a[1]) - (b[1] - a[1]) * (x - a[0])
+        };
+        for corner in &[(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+            assert!(edge(vertices[0], vertices[1], *corner) >= 0.);
+            assert!(edge(vertices[1], vertices[2], *corner) >= 0.);
+            assert!(edge(vertices[2], vertices[0], *corner) >= 0.);
+        }
+        let tex_coordinates = FULLSCREEN_TRIANGLE_TEX_COORDINATES.chunks(2);
+        for (position, tex_coordinate) in vertices.iter().zip(tex_coordinates) {
+            assert_eq!(tex_coordinate[0], (position[0] + 1.) / 2.);
+            assert_eq!(tex_coordinate[1], (position[1] + 1.) / 2.);
+        }
+    }
+
+    #[test]
+    fn missing_and_oversized_attributes_are_reported() {
+        let expected_attributes = vec![
+            (String::from("a_position"), ShaderDataType::Vector3),
+            (String::from("a_normal"), ShaderDataType::Vector3),
+            (String::from("a_tex_coordinates"), ShaderDataType::Vector2),
+        ];
+        let buffer_types = [
+            ("a_position", &ShaderDataType::Vector2),
+            ("a_tex_coordinates", &ShaderDataType::Vector4),
+        ];
+        let warnings =
+            get_attribute_warnings("mesh", &buffer_types, "material", &expected_attributes);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("no a_normal buffer"));
+        assert!(warnings[1].contains("4 components per vertex in its a_tex_coordinates"));
+    }
+
+    #[test]
+    fn removed_attributes_never_include_positions() {
+        let names = [
+            crate::utils::constants::VERTEX_BUFFER_NAME,
+            crate::utils::constants::NORMAL_BUFFER_NAME,
+            crate::utils::constants::UV_BUFFER_NAME,
+        ];
+        let stripped = [crate::utils::constants::UV_BUFFER_NAME];
+        assert_eq!(
+            get_removed_attributes(&names, |name| stripped.contains(&name)),
+            stripped
+        );
+        let retained = [crate::utils::constants::NORMAL_BUFFER_NAME];
+        assert_eq!(
+            get_removed_attributes(&names, |name| !retained.contains(&name)),
+            stripped
+        );
+        assert!(get_removed_attributes(&names[..1], |_| true).is_empty());
+    }
+}
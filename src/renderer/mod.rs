@@ -10,22 +10,313 @@ mod mesh_data;
 
 mod light_repository;
 
-pub use buffer::Buffer;
+mod sampler;
+
+mod stencil;
+
+mod gl_state;
+
+pub use buffer::{Buffer, BufferUsage};
 pub use light_repository::{LightConfiguration, LightRepository};
-pub use material::{Material, MaterialInstance};
+pub use material::{Material, MaterialInstance, Precision, UniformObserver};
 pub use mesh_data::MeshData;
-pub use uniform::{GlobalUniformLocations, Uniform, UniformValue};
+pub use sampler::SamplerPolicy;
+pub use stencil::StencilConfig;
+pub use uniform::{GlobalUniformLocations, LightUniformLocations, Uniform, UniformValue};
 
 use crate::asset::AssetRegistry;
-use crate::component::{Camera, Transform};
+use crate::component::{Camera, Mesh, Transform};
+use crate::error::Error;
 use crate::scene::FileType;
-use crate::utils::console_error;
-use std::cell::RefCell;
+use crate::utils::{console_error, console_warn, PixelLayout};
+use gl_state::GlState;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::HashMap;
 use std::rc::Rc;
 use web_sys::{HtmlCanvasElement, HtmlImageElement, WebGlRenderingContext};
 
-pub type SortedMeshes<'a> = HashMap<&'a usize, HashMap<&'a usize, Vec<(&'a usize, &'a Transform)>>>;
+pub type SortedMeshes<'a> = HashMap<&'a usize, HashMap<&'a usize, Vec<(&'a Mesh, &'a Transform)>>>;
+
+/// Meshes to draw with one material, grouped by `MeshData` id.
+type MeshDataList<'a> = Vec<(&'a usize, Vec<(&'a Mesh, &'a Transform)>)>;
+
+/// Sort key of an opaque mesh, ordering draws so that state changes are minimized:
+/// shader program first, then `MeshData` buffers, texture set and `MaterialInstance` uniforms.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaterialSortKey {
+    /// Program drawing the mesh, as `(drawn with a fallback program, material id)`.
+    /// Materials drawn with the shared fallback program sort last, together.
+    pub program: (bool, usize),
+
+    /// Id of the mesh's `MeshData`
+    pub mesh_data: usize,
+
+    /// Sorted registry indices of the textures bound by the mesh's `MaterialInstance`
+    pub texture_set: Vec<usize>,
+
+    /// Id of the mesh's `MaterialInstance`
+    pub material_instance: usize,
+}
+
+/// Sorts opaque meshes by their `MaterialSortKey`, then groups them like `group_meshes`.
+fn sort_by_material_key<'a>(
+    mut meshes: Vec<(
+        MaterialSortKey,
+        &'a usize,
+        &'a usize,
+        &'a Mesh,
+        &'a Transform,
+    )>,
+) -> Vec<(usize, MeshDataList<'a>)> {
+    meshes.sort_by(|a, b| a.0.cmp(&b.0));
+    group_meshes(
+        meshes
+            .into_iter()
+            .map(|(_, material_id, mesh_data_id, mesh, transform)| {
+                (material_id, mesh_data_id, mesh, transform)
+            }),
+    )
+}
+
+/// Groups consecutive meshes sharing a material and `MeshData`, so that they are bound once.
+fn group_meshes<'a, I>(meshes: I) -> Vec<(usize, MeshDataList<'a>)>
+where
+    I: Iterator<Item = (&'a usize, &'a usize, &'a Mesh, &'a Transform)>,
+{
+    let mut sorted: Vec<(usize, MeshDataList)> = Vec::new();
+    for (material_id, mesh_data_id, mesh, transform) in meshes {
+        match sorted.last_mut() {
+            Some((last_material_id, mesh_data_list)) if last_material_id == material_id => {
+                match mesh_data_list.last_mut() {
+                    Some((last_mesh_data_id, transforms)) if *last_mesh_data_id == mesh_data_id => {
+                        transforms.push((mesh, transform))
+                    }
+                    _ => mesh_data_list.push((mesh_data_id, vec![(mesh, transform)])),
+                }
+            }
+            _ => sorted.push((*material_id, vec![(mesh_data_id, vec![(mesh, transform)])])),
+        }
+    }
+    sorted
+}
+
+/// Orders transparent meshes from the farthest to the closest to a camera with `view_matrix`,
+/// using the view-space depth of their origin plus their sort bias.  
+/// Consecutive meshes sharing a material and `MeshData` are grouped so that they are bound once.
+fn sort_back_to_front<'a>(
+    view_matrix: &Matrix4<f32>,
+    materials: Vec<(&'a usize, MeshDataList<'a>)>,
+) -> Vec<(usize, MeshDataList<'a>)> {
+    let mut meshes = Vec::new();
+    for (material_id, mesh_data_list) in materials {
+        for (mesh_data_id, transforms) in mesh_data_list {
+            for (mesh, transform) in transforms {
+                let origin = transform.get_world_matrix() * Vector4::new(0.0, 0.0, 0.0, 1.0);
+                let depth = -(view_matrix * origin).z + mesh.get_sort_bias();
+                meshes.push((depth, material_id, mesh_data_id, mesh, transform));
+            }
+        }
+    }
+    meshes.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    group_meshes(
+        meshes
+            .into_iter()
+            .map(|(_, material_id, mesh_data_id, mesh, transform)| {
+                (material_id, mesh_data_id, mesh, transform)
+            }),
+    )
+}
+
+/// Returns the view matrix, position and projection matrix uploaded for `camera`.  
+/// With `camera_relative`, the camera is placed at the origin. See `Renderer::set_camera_relative_rendering`.
+fn get_camera_uniform_values(
+    camera: &Camera,
+    camera_relative: bool,
+) -> (Matrix4<f32>, Vector3<f32>, Matrix4<f32>) {
+    if camera_relative {
+        (
+            camera.get_view_rotation_matrix(),
+            Vector3::zeros(),
+            camera.get_projection_matrix(),
+        )
+    } else {
+        (
+            camera.get_view_matrix(),
+            camera.get_eye_position().coords,
+            camera.get_projection_matrix(),
+        )
+    }
+}
+
+/// Returns `world_matrix` with its translation expressed relative to `origin`.
+fn get_relative_world_matrix(world_matrix: &Matrix4<f32>, origin: &Vector3<f32>) -> Matrix4<f32> {
+    let mut relative_matrix = *world_matrix;
+    relative_matrix[(0, 3)] -= origin.x;
+    relative_matrix[(1, 3)] -= origin.y;
+    relative_matrix[(2, 3)] -= origin.z;
+    relative_matrix
+}
+
+/// Returns the message of `result`'s error if it differs from `last_error`, which is updated.
+fn get_new_error(last_error: &mut Option<String>, result: Result<(), Error>) -> Option<String> {
+    match result {
+        Err(error) => {
+            let message = error.to_string();
+            if last_error.as_ref() == Some(&message) {
+                None
+            } else {
+                last_error.replace(message.clone());
+                Some(message)
+            }
+        }
+        Ok(()) => {
+            *last_error = None;
+            None
+        }
+    }
+}
+
+/// Returns `true` if a frame must be drawn: always, unless `redraw_on_demand` is enabled
+/// and nothing marked the renderer `dirty` since the last rendered frame.
+fn get_needs_redraw(redraw_on_demand: bool, dirty: bool) -> bool {
+    !redraw_on_demand || dirty
+}
+
+/// Returns the index count and the byte offset of the first index to draw `mesh`
+/// from a `MeshData` with `vertex_count` indices of type `index_type`.
+fn get_draw_arguments(mesh: &Mesh, vertex_count: i32, index_type: u32) -> (i32, i32) {
+    let (first_index, index_count) = mesh.get_clamped_draw_range(vertex_count);
+    let index_size = if index_type == WebGlRenderingContext::UNSIGNED_INT {
+        4
+    } else {
+        2
+    };
+    (index_count, first_index * index_size)
+}
+
+/// Calls `draw` with `depth_range` applied through `set_depth_range`, then restores the
+/// default `[0, 1]` range. Without a depth range, `draw` is called alone.
+fn draw_in_depth_range(
+    depth_range: Option<(f32, f32)>,
+    mut set_depth_range: impl FnMut(f32, f32),
+    draw: impl FnOnce(),
+) {
+    if let Some((near, far)) = depth_range {
+        set_depth_range(near, far);
+        draw();
+        set_depth_range(0.0, 1.0);
+    } else {
+        draw();
+    }
+}
+
+/// Turns a `getError` code raised while rendering into an error.  
+/// `CONTEXT_LOST_WEBGL` is reported as `Error::ContextLost`.
+fn check_gl_error(gl_error: u32) -> Result<(), Error> {
+    match gl_error {
+        WebGlRenderingContext::NO_ERROR => Ok(()),
+        WebGlRenderingContext::CONTEXT_LOST_WEBGL => Err(Error::ContextLost),
+        _ => Err(Error::Other(format!(
+            "WebGL error 0x{:x} was raised while rendering objects.",
+            gl_error
+        ))),
+    }
+}
+
+/// Returns an error unless `end` is positive and `start` is greater than `end`.
+fn check_near_fade(start: f32, end: f32) -> Result<(), Error> {
+    if end < 0.0 || start <= end {
+        Err(Error::Uniform(format!(
+            "Invalid near fade distances: start ({}) must be greater than end ({}), and end positive.",
+            start, end
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the near fade distances uploaded for a material, only if it is `transparent`.  
+/// When the fade is disabled, distances are chosen so that the fade factor is always `1`.
+fn get_near_fade_value(near_fade: Option<(f32, f32)>, transparent: bool) -> Option<Vector2<f32>> {
+    if transparent {
+        let (start, end) = near_fade.unwrap_or((0.0, -1.0));
+        Some(Vector2::new(start, end))
+    } else {
+        None
+    }
+}
+
+/// Returns whether `CULL_FACE` and `DEPTH_TEST` are enabled for a frame.  
+/// Depth testing is always off when debugging overdraw.
+fn get_capability_states(
+    cull_face: bool,
+    depth_test: bool,
+    debug_overdraw: bool,
+) -> [(u32, bool); 2] {
+    [
+        (WebGlRenderingContext::CULL_FACE, cull_face),
+        (
+            WebGlRenderingContext::DEPTH_TEST,
+            depth_test && !debug_overdraw,
+        ),
+    ]
+}
+
+/// Returns the blend factors for drawing the opaque or `transparent_pass` meshes, or `None`
+/// to draw them without blending.  
+/// When debugging overdraw, every mesh is blended additively.
+fn get_blend_func(debug_overdraw: bool, transparent_pass: bool) -> Option<(u32, u32)> {
+    if debug_overdraw {
+        Some((WebGlRenderingContext::ONE, WebGlRenderingContext::ONE))
+    } else if transparent_pass {
+        Some((
+            WebGlRenderingContext::SRC_ALPHA,
+            WebGlRenderingContext::ONE_MINUS_SRC_ALPHA,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Returns the depth function and depth mask for drawing meshes.  
+/// With a depth prepass, opaque meshes first write their depth in the `depth_only` pass,
+/// then are shaded where their depth is `EQUAL` to it, without writing it again.
+/// Transparent meshes are depth tested but never write their depth.
+fn get_depth_state(depth_prepass: bool, depth_only: bool, transparent: bool) -> (u32, bool) {
+    if depth_prepass && !depth_only && !transparent {
+        (WebGlRenderingContext::EQUAL, false)
+    } else {
+        (WebGlRenderingContext::LESS, !transparent)
+    }
+}
+
+/// Returns an error if `material` has no linked program, in which case its meshes are
+/// rendered with the error material.
+fn check_material_compiled(material: &Material) -> Result<(), Error> {
+    if material.get_program().is_some() {
+        Ok(())
+    } else {
+        Err(Error::ShaderCompile(format!(
+            "Material {} is not compiled and was rendered with the error material.",
+            material.get_id()
+        )))
+    }
+}
+
+/// Returns the resolution scale following `quality` for a smoothed frame time of
+/// `average_frame_time` milliseconds: one step lower when over 110% of `target`,
+/// one step higher when under 80% of it, or unchanged in between.
+fn get_stepped_quality(quality: f32, average_frame_time: f32, target: f32) -> f32 {
+    let step = crate::utils::constants::ADAPTIVE_QUALITY_STEP;
+    if average_frame_time > target * 1.1 {
+        (quality - step).max(crate::utils::constants::MIN_ADAPTIVE_QUALITY)
+    } else if average_frame_time < target * 0.8 {
+        (quality + step).min(1.0)
+    } else {
+        quality
+    }
+}
 
 /// ## Renderer
 ///
@@ -47,6 +338,82 @@ pub struct Renderer {
 
     /// Asset registry instance for use with this renderer
     asset_registry: AssetRegistry,
+
+    /// Color the canvas is cleared to before rendering.
+    clear_color: Vector4<f32>,
+
+    /// Buffers cleared before rendering, as a combination of `COLOR_BUFFER_BIT`,
+    /// `DEPTH_BUFFER_BIT` and `STENCIL_BUFFER_BIT`. `0` disables clearing.
+    clear_mask: u32,
+
+    /// Sampler policy applied to every texture registered without an explicit one.
+    default_sampler: SamplerPolicy,
+
+    /// Magenta material substituted for materials that failed to compile.
+    error_material: Rc<RefCell<Material>>,
+
+    /// Elapsed time in seconds, uploaded to the `u_time` uniform.
+    time: f32,
+
+    /// Time in seconds since the previous frame, uploaded to the `u_delta_time` uniform.
+    delta_time: f32,
+
+    /// if `true`, objects and lights are uploaded relative to the camera position.
+    camera_relative: bool,
+
+    /// Distances from the camera at which transparent objects start and finish fading out.
+    near_fade: Option<(f32, f32)>,
+
+    /// Vertex attribute arrays currently enabled in the context, so that arrays left
+    /// enabled by a previous mesh can be disabled.
+    enabled_attributes: RefCell<Vec<u32>>,
+
+    /// Fullscreen triangle shared by fullscreen passes, created on first use.
+    fullscreen_triangle: RefCell<Option<Rc<RefCell<MeshData>>>>,
+
+    /// Number of shader program switches in the color passes of the last call to `render_objects`.
+    /// Switches during the depth prepass are not counted.
+    material_switch_count: Cell<u32>,
+
+    /// Material whose program was last used in the color passes, to count program switches.
+    current_program_material: Cell<Option<usize>>,
+
+    /// if `true`, opaque meshes are first rendered to the depth buffer only, then shaded
+    /// with an `EQUAL` depth test so that each pixel is shaded once.
+    depth_prepass: bool,
+
+    /// if `true`, every mesh is drawn with a flat material and additive blending to visualize overdraw.
+    debug_overdraw: bool,
+
+    /// Flat material used to visualize overdraw.
+    overdraw_material: Rc<RefCell<Material>>,
+
+    /// if `true`, frames are only rendered when something changed since the last one.
+    redraw_on_demand: bool,
+
+    /// if `true`, something changed since the last rendered frame.
+    dirty: Cell<bool>,
+
+    /// Frame time in milliseconds the adaptive quality controller tries to stay under, if enabled.
+    adaptive_quality_target: Option<f32>,
+
+    /// Scale applied to the canvas resolution, between `MIN_ADAPTIVE_QUALITY` and `1.0`.
+    quality: f32,
+
+    /// Smoothed frame time in milliseconds, used by the adaptive quality controller.
+    average_frame_time: f32,
+
+    /// if `true`, back faces are culled. Enabled by default.
+    cull_face: bool,
+
+    /// if `true`, fragments are depth tested. Enabled by default.
+    depth_test: bool,
+
+    /// GL states saved with `push_state`, restored in reverse order by `pop_state`.
+    state_stack: RefCell<Vec<GlState>>,
+
+    /// Message of the last error logged by `render_objects_or_warn`, to avoid repeating it each frame.
+    last_render_error: RefCell<Option<String>>,
 }
 
 impl Renderer {
@@ -57,11 +424,44 @@ impl Renderer {
         canvas: HtmlCanvasElement,
         context: WebGlRenderingContext,
     ) -> Renderer {
+        let mut asset_registry = AssetRegistry::new();
+        if let Some(max_texture_units) = context
+            .get_parameter(WebGlRenderingContext::MAX_TEXTURE_IMAGE_UNITS)
+            .ok()
+            .and_then(|value| value.as_f64())
+        {
+            asset_registry.set_max_texture_units(max_texture_units as u32);
+        }
         Renderer {
             webgl_context: context,
             canvas: canvas,
             main_camera: Rc::new(RefCell::new(camera)),
-            asset_registry: AssetRegistry::new(),
+            asset_registry: asset_registry,
+            clear_color: Vector4::new(0., 0., 0., 0.),
+            clear_mask: WebGlRenderingContext::COLOR_BUFFER_BIT
+                | WebGlRenderingContext::DEPTH_BUFFER_BIT,
+            default_sampler: Default::default(),
+            error_material: Rc::new(RefCell::new(Material::new_error_material())),
+            time: 0.0,
+            delta_time: 0.0,
+            camera_relative: false,
+            near_fade: None,
+            enabled_attributes: RefCell::new(Vec::new()),
+            fullscreen_triangle: RefCell::new(None),
+            material_switch_count: Cell::new(0),
+            current_program_material: Cell::new(None),
+            depth_prepass: false,
+            debug_overdraw: false,
+            overdraw_material: Rc::new(RefCell::new(Material::new_overdraw_material())),
+            redraw_on_demand: false,
+            dirty: Cell::new(true),
+            adaptive_quality_target: None,
+            quality: 1.0,
+            average_frame_time: 0.0,
+            cull_face: true,
+            depth_test: true,
+            state_stack: RefCell::new(Vec::new()),
+            last_render_error: RefCell::new(None),
         }
     }
 
@@ -69,17 +469,216 @@ impl Renderer {
         &self.webgl_context
     }
 
+    /// Replaces the camera used to render the scene, e.g. to switch between an editor view
+    /// and a game view. The camera's aspect ratio is matched to the canvas.
+    pub fn set_camera(&mut self, camera: Rc<RefCell<Camera>>) {
+        let ratio = self.canvas.client_width() as f32 / self.canvas.client_height() as f32;
+        camera.borrow_mut().set_aspect_ratio(ratio);
+        self.main_camera = camera;
+        self.set_dirty();
+    }
+
+    /// Returns the camera used to render the scene.
+    pub fn get_camera(&self) -> Rc<RefCell<Camera>> {
+        self.main_camera.clone()
+    }
+
+    /// Sets the elapsed time and frame delta time, in seconds.  
+    /// They are uploaded to every material declaring the `u_time` and `u_delta_time` uniforms.
+    pub fn set_time(&mut self, time: f32, delta_time: f32) {
+        self.time = time;
+        self.delta_time = delta_time;
+        if let Some(target) = self.adaptive_quality_target {
+            self.update_quality(delta_time * 1000.0, target);
+        }
+    }
+
+    /// Enables the adaptive quality controller, which lowers the render resolution when frames
+    /// take longer than `target_ms` milliseconds, and raises it back when there is headroom.  
+    /// The canvas is resized accordingly on the next `resize_canvas`, and upscaled by the browser.
+    pub fn set_adaptive_quality(&mut self, target_ms: f32) {
+        self.adaptive_quality_target = Some(target_ms);
+        self.average_frame_time = target_ms;
+    }
+
+    /// Disables the adaptive quality controller and restores the full render resolution.
+    pub fn disable_adaptive_quality(&mut self) {
+        self.adaptive_quality_target = None;
+        self.quality = 1.0;
+    }
+
+    /// Returns the current resolution scale picked by the adaptive quality controller, `1.0` meaning full resolution.
+    pub fn get_quality(&self) -> f32 {
+        self.quality
+    }
+
+    /// Smoothes the frame time and steps the quality down when it is over budget,
+    /// or up when it is well under. The smoothed time is reset after each step so that
+    /// the effect of a change is measured before the next one.
+    fn update_quality(&mut self, frame_time: f32, target: f32) {
+        self.average_frame_time = self.average_frame_time * 0.9 + frame_time * 0.1;
+        let quality = get_stepped_quality(self.quality, self.average_frame_time, target);
+        if quality != self.quality {
+            self.quality = quality;
+            self.average_frame_time = target;
+        }
+    }
+
+    /// Sets the color the canvas is cleared to before rendering. Defaults to transparent black.
+    pub fn set_clear_color(&mut self, color: Vector4<f32>) {
+        self.clear_color = color;
+    }
+
+    /// Sets which buffers are cleared before rendering, as a combination of
+    /// `COLOR_BUFFER_BIT`, `DEPTH_BUFFER_BIT` and `STENCIL_BUFFER_BIT`.  
+    /// Defaults to color and depth. Use `0` to keep the previous frame's content.  
+    /// The renderer draws in a single pass to the canvas, so this is the clear of that pass.
+    /// Add `STENCIL_BUFFER_BIT` when using materials with a `StencilConfig`.
+    pub fn set_clear_mask(&mut self, mask: u32) {
+        self.clear_mask = mask;
+    }
+
+    /// Enables or disables camera-relative rendering.  
+    /// When enabled, world transforms and point light positions are uploaded relative to
+    /// the camera position, and the view matrix places the camera at the origin.
+    /// This keeps uploaded values small and avoids jittering far from the world origin.
+    pub fn set_camera_relative_rendering(&mut self, camera_relative: bool) {
+        self.camera_relative = camera_relative;
+    }
+
+    /// Makes transparent materials fade out as they approach the camera: fully visible
+    /// beyond `start` and invisible closer than `end`.  
+    /// The distances are uploaded to the `u_near_fade` uniform, to be used with the
+    /// `near_fade` shader function from `shaders/src/near_fade.glsl`.
+    pub fn set_near_fade(&mut self, start: f32, end: f32) -> Result<(), Error> {
+        check_near_fade(start, end)?;
+        self.near_fade = Some((start, end));
+        Ok(())
+    }
+
+    /// Disables the near fade of transparent materials.
+    pub fn disable_near_fade(&mut self) {
+        self.near_fade = None;
+    }
+
+    /// Enables or disables back-face culling, e.g. for double-sided foliage. Enabled by default.
+    pub fn set_cull_face(&mut self, cull_face: bool) {
+        self.cull_face = cull_face;
+    }
+
+    /// Returns `true` if back faces are culled.
+    pub fn get_cull_face(&self) -> bool {
+        self.cull_face
+    }
+
+    /// Enables or disables the depth test, e.g. for 2D overlays. Enabled by default.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    /// Returns `true` if fragments are depth tested, unless debugging overdraw.
+    pub fn get_depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    /// Enables or disables the depth prepass.  
+    /// When enabled, opaque meshes are rendered twice: once to fill the depth buffer with
+    /// color writes disabled, then with depth writes disabled and an `EQUAL` depth test,
+    /// so that expensive fragment shaders run once per pixel. Useful for scenes with a lot of overdraw.  
+    /// The prepass draws with each material's depth material, which shares its vertex shader,
+    /// with `gl_Position` declared `invariant` so that depths are equal in both passes,
+    /// but skips its fragment shader. Materials
+    /// without one (see `Material::get_depth_material`) run their own shaders in the prepass.
+    pub fn enable_depth_prepass(&mut self, depth_prepass: bool) {
+        self.depth_prepass = depth_prepass;
+    }
+
+    /// Enables or disables the overdraw visualization.  
+    /// When enabled, every mesh is drawn with a flat shader adding a small constant to
+    /// each pixel, with depth testing off and additive blending, so that areas drawn many
+    /// times appear brighter. Meant for debugging; the depth prepass is skipped meanwhile.
+    pub fn set_debug_overdraw(&mut self, debug_overdraw: bool) {
+        self.debug_overdraw = debug_overdraw;
+    }
+
+    /// Enables or disables redraw on demand, for mostly static scenes.  
+    /// When enabled, `render_objects` skips all GL work unless the renderer was marked dirty
+    /// since the last rendered frame. Canvas resizes, camera changes, asset registrations,
+    /// and entity, transform and mesh changes made through the `Scene` mark it automatically;
+    /// other changes, like uniform updates, renderer settings or materials animated with
+    /// `u_time`, need a call to `set_dirty`.
+    pub fn set_redraw_on_demand(&mut self, redraw_on_demand: bool) {
+        self.redraw_on_demand = redraw_on_demand;
+        self.dirty.set(true);
+    }
+
+    /// Requests a new frame when redraw on demand is enabled.
+    pub fn set_dirty(&self) {
+        self.dirty.set(true);
+    }
+
+    /// Returns `true` if the next call to `render_objects` will draw the scene.
+    pub fn needs_redraw(&self) -> bool {
+        get_needs_redraw(self.redraw_on_demand, self.dirty.get())
+    }
+
+    /// Origin of the coordinates uploaded to the shaders: the camera position
+    /// with camera-relative rendering, the world origin otherwise.
+    fn get_render_origin(&self) -> Vector3<f32> {
+        if self.camera_relative {
+            self.main_camera.borrow().get_eye_position().coords
+        } else {
+            Vector3::zeros()
+        }
+    }
+
+    /// Saves the GL state the renderer relies on: bound program, blending and blend function,
+    /// depth test and settings, face culling, color mask, stencil test and settings, viewport,
+    /// active texture unit and its bound texture, and bound buffers.  
+    /// Calls to other GL code should be bracketed with `push_state` and `pop_state`.
+    pub fn push_state(&self) {
+        self.state_stack
+            .borrow_mut()
+            .push(GlState::capture(&self.webgl_context));
+    }
+
+    /// Restores the GL state saved by the matching `push_state`.
+    pub fn pop_state(&self) -> Result<(), Error> {
+        match self.state_stack.borrow_mut().pop() {
+            Some(state) => {
+                state.restore(&self.webgl_context);
+                Ok(())
+            }
+            None => Err(Error::Other(String::from(
+                "pop_state was called without a matching push_state.",
+            ))),
+        }
+    }
+
+    /// Returns the `MeshData` of a triangle covering the whole screen, creating it on first use.  
+    /// See `MeshData::new_fullscreen_triangle`.
+    pub fn get_fullscreen_triangle(&self) -> Rc<RefCell<MeshData>> {
+        self.fullscreen_triangle
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                Rc::new(RefCell::new(MeshData::new_fullscreen_triangle(
+                    &self.webgl_context,
+                )))
+            })
+            .clone()
+    }
+
     /// Resizes the canvas internal size to match the display resolution and ratio.  
     /// Also updates the WebGl Viewport to match.
     ///
     /// ⚠️ might be removed in favor of all-JS version.
     pub fn resize_canvas(&mut self) -> () {
-        let pixel_ratio = web_sys::window().unwrap().device_pixel_ratio() as f32;
+        let pixel_ratio = web_sys::window().unwrap().device_pixel_ratio() as f32 * self.quality;
         let display_width = self.canvas.client_width() as u32;
         let display_height = self.canvas.client_height() as u32;
         let resolution_x = (display_width as f32 * pixel_ratio) as u32;
         let resolution_y = (display_height as f32 * pixel_ratio) as u32;
-        
+
         if self.canvas.width() != resolution_x || self.canvas.height() != resolution_y {
             self.canvas.set_width(resolution_x);
             self.canvas.set_height(resolution_y);
@@ -87,112 +686,416 @@ impl Renderer {
             self.main_camera.borrow_mut().set_aspect_ratio(ratio);
             self.webgl_context
                 .viewport(0, 0, resolution_x as i32, resolution_y as i32);
+            self.set_dirty();
         }
     }
 
     /// Renders all the objects registered in the Mesh Repository and prints them to the Canvas.component
     ///
-    /// The opaque objects will be rendered before the transparent ones (ordered by depth), and every object will be sorted
-    /// by `Material` id to optimize performance.
-    // ⭕ TODO handle semi-transparent objects separately
-    pub fn render_objects(&self, sorted_meshes: SortedMeshes, light_repository: &LightRepository) {
-        self.webgl_context.clear_color(0., 0., 0., 0.);
-        self.webgl_context.clear(
-            WebGlRenderingContext::COLOR_BUFFER_BIT | WebGlRenderingContext::DEPTH_BUFFER_BIT,
-        );
-        self.webgl_context.enable(WebGlRenderingContext::CULL_FACE);
-        self.webgl_context.enable(WebGlRenderingContext::DEPTH_TEST);
-        for (material_id, mesh_hash_map) in sorted_meshes {
-            self.draw_meshes_using_material(
-                material_id.to_owned(),
-                mesh_hash_map,
-                light_repository,
+    /// The opaque objects will be rendered before the transparent ones, which are alpha blended
+    /// without writing to the depth buffer. Opaque objects are sorted
+    /// by `Material` id, then by `MeshData` id, so that each program and set of buffers is bound once per frame,
+    /// in the same order every frame. Transparent objects are drawn back to front. See `sort_back_to_front`.
+    ///
+    /// The whole frame is always drawn, but the first error met is returned: unregistered assets,
+    /// materials rendered with the error material because they are not compiled, failed uniform
+    /// uploads, or a WebGL error raised during the draw calls.
+    pub fn render_objects(
+        &self,
+        sorted_meshes: SortedMeshes,
+        light_repository: &LightRepository,
+    ) -> Result<(), Error> {
+        if !self.needs_redraw() {
+            return Ok(());
+        }
+        self.dirty.set(false);
+        if self.clear_mask != 0 {
+            self.webgl_context.clear_color(
+                self.clear_color.x,
+                self.clear_color.y,
+                self.clear_color.z,
+                self.clear_color.w,
             );
+            self.webgl_context.clear(self.clear_mask);
+        }
+        for (capability, enabled) in
+            &get_capability_states(self.cull_face, self.depth_test, self.debug_overdraw)
+        {
+            if *enabled {
+                self.webgl_context.enable(*capability);
+            } else {
+                self.webgl_context.disable(*capability);
+            }
+        }
+        self.set_blend_func(get_blend_func(self.debug_overdraw, false));
+        self.material_switch_count.set(0);
+        self.current_program_material.set(None);
+        let materials: Vec<(&usize, MeshDataList)> = sorted_meshes
+            .into_iter()
+            .map(|(material_id, mesh_hash_map)| (material_id, mesh_hash_map.into_iter().collect()))
+            .collect();
+        let (transparent_materials, materials): (Vec<_>, Vec<_>) =
+            materials.into_iter().partition(|(material_id, _)| {
+                self.asset_registry
+                    .get_material_with_index(**material_id)
+                    .map(|material| material.borrow().is_transparent())
+                    .unwrap_or(false)
+            });
+        let materials = sort_by_material_key(self.get_material_sort_keys(materials));
+        let mut result = Ok(());
+        if self.depth_prepass && !self.debug_overdraw {
+            self.webgl_context.color_mask(false, false, false, false);
+            for (material_id, mesh_data_list) in &materials {
+                result = result.and(self.draw_meshes_using_material(
+                    *material_id,
+                    mesh_data_list,
+                    light_repository,
+                    true,
+                ));
+            }
+            self.webgl_context.color_mask(true, true, true, true);
+        }
+        for (material_id, mesh_data_list) in &materials {
+            result = result.and(self.draw_meshes_using_material(
+                *material_id,
+                mesh_data_list,
+                light_repository,
+                false,
+            ));
+        }
+        self.set_blend_func(get_blend_func(self.debug_overdraw, true));
+        let view_matrix = self.main_camera.borrow().get_view_matrix();
+        for (material_id, mesh_data_list) in sort_back_to_front(&view_matrix, transparent_materials)
+        {
+            result = result.and(self.draw_meshes_using_material(
+                material_id,
+                &mesh_data_list,
+                light_repository,
+                false,
+            ));
+        }
+        result = result.and(check_gl_error(self.webgl_context.get_error()));
+        self.webgl_context.depth_func(WebGlRenderingContext::LESS);
+        self.webgl_context.depth_mask(true);
+        self.webgl_context.disable(WebGlRenderingContext::BLEND);
+        result
+    }
+
+    /// Enables blending with the given source and destination factors, or disables it with `None`.
+    fn set_blend_func(&self, blend_func: Option<(u32, u32)>) {
+        if let Some((source_factor, destination_factor)) = blend_func {
+            self.webgl_context.enable(WebGlRenderingContext::BLEND);
+            self.webgl_context
+                .blend_func(source_factor, destination_factor);
+        } else {
+            self.webgl_context.disable(WebGlRenderingContext::BLEND);
+        }
+    }
+
+    /// Same as `render_objects`, but logs the returned error as a warning instead.  
+    /// An error is only logged when it differs from the last one logged, so that an error
+    /// repeated every frame is logged once.
+    pub fn render_objects_or_warn(
+        &self,
+        sorted_meshes: SortedMeshes,
+        light_repository: &LightRepository,
+    ) {
+        let result = self.render_objects(sorted_meshes, light_repository);
+        if let Some(message) = get_new_error(&mut self.last_render_error.borrow_mut(), result) {
+            console_warn(&message);
+        }
+    }
+
+    /// Returns the sort keys of opaque meshes, to be ordered with `sort_by_material_key`.
+    fn get_material_sort_keys<'a>(
+        &self,
+        materials: Vec<(&'a usize, MeshDataList<'a>)>,
+    ) -> Vec<(
+        MaterialSortKey,
+        &'a usize,
+        &'a usize,
+        &'a Mesh,
+        &'a Transform,
+    )> {
+        let mut meshes = Vec::new();
+        for (material_id, mesh_data_list) in materials {
+            let uses_fallback = self.debug_overdraw
+                || self
+                    .asset_registry
+                    .get_material_with_index(*material_id)
+                    .map(|material| material.borrow().get_program().is_none())
+                    .unwrap_or(true);
+            for (mesh_data_id, transforms) in mesh_data_list {
+                for (mesh, transform) in transforms {
+                    let texture_set = self
+                        .asset_registry
+                        .get_material_instance_with_index(*mesh.get_material_instance_id())
+                        .map(|instance| instance.borrow().get_texture_set().to_vec())
+                        .unwrap_or_default();
+                    let key = MaterialSortKey {
+                        program: (uses_fallback, *material_id),
+                        mesh_data: *mesh_data_id,
+                        texture_set,
+                        material_instance: *mesh.get_material_instance_id(),
+                    };
+                    meshes.push((key, material_id, mesh_data_id, mesh, transform));
+                }
+            }
         }
+        meshes
     }
 
+    /// Draws every mesh using a material. With `depth_only`, only opaque meshes are drawn,
+    /// for the depth prepass, using the material's depth material when it has one.
     fn draw_meshes_using_material(
         &self,
         material_id: usize,
-        mesh_hash_map: HashMap<&usize, Vec<(&usize, &Transform)>>,
+        mesh_data_list: &[(&usize, Vec<(&Mesh, &Transform)>)],
         light_repository: &LightRepository,
-    ) {
-        if let Some(material) = self.asset_registry.get_material_with_index(material_id) {
+        depth_only: bool,
+    ) -> Result<(), Error> {
+        if let Some(registered_material) = self.asset_registry.get_material_with_index(material_id)
+        {
+            let transparent = registered_material.borrow().is_transparent();
+            if depth_only && transparent {
+                return Ok(());
+            }
+            let (depth_func, depth_mask) = get_depth_state(
+                self.depth_prepass && !self.debug_overdraw,
+                depth_only,
+                transparent,
+            );
+            self.webgl_context.depth_func(depth_func);
+            self.webgl_context.depth_mask(depth_mask);
+            let mut result = check_material_compiled(&registered_material.borrow());
+            let compiled = result.is_ok() && !self.debug_overdraw;
+            let material = if compiled {
+                registered_material
+            } else {
+                let builtin_material = if self.debug_overdraw {
+                    &self.overdraw_material
+                } else {
+                    &self.error_material
+                };
+                match self.get_builtin_material(builtin_material) {
+                    Some(builtin_material) => builtin_material,
+                    None => {
+                        return Err(Error::ShaderCompile(format!(
+                            "Meshes using material {} were not rendered because no fallback material could be compiled.",
+                            material_id
+                        )))
+                    }
+                }
+            };
+            let stencil = material.borrow().get_stencil();
+            let depth_material = if depth_only && compiled {
+                material.borrow().get_depth_material()
+            } else {
+                None
+            };
+            let instance_uniforms = depth_material.is_none();
+            let material = depth_material.unwrap_or(material);
             self.webgl_context
                 .use_program(Some(&material.borrow().get_program().as_ref().unwrap()));
-            material
-                .borrow()
-                .set_uniforms_to_context(&self.webgl_context)
-                .ok();
-            self.set_camera_uniforms(material.clone()).ok();
-            self.set_lights_uniforms(material.clone(), light_repository)
-                .ok();
-            for (mesh_data_id, transforms) in mesh_hash_map {
-                self.draw_meshes_using_mesh_data(&mesh_data_id, material.clone(), transforms);
+            let program_material = Rc::as_ptr(&material) as usize;
+            if !depth_only && self.current_program_material.get() != Some(program_material) {
+                self.current_program_material.set(Some(program_material));
+                self.material_switch_count
+                    .set(self.material_switch_count.get() + 1);
+            }
+            result = result.and(
+                material
+                    .borrow()
+                    .set_uniforms_to_context(&self.webgl_context),
+            );
+            result = result.and(self.set_camera_uniforms(material.clone()));
+            result = result.and(self.set_time_uniforms(material.clone()));
+            if compiled && !depth_only {
+                result = result.and(self.set_lights_uniforms(material.clone(), light_repository));
+                if let Some(near_fade) =
+                    get_near_fade_value(self.near_fade, material.borrow().is_transparent())
+                {
+                    result = result.and(self.set_near_fade_uniform(material.clone(), near_fade));
+                }
             }
+            if let Some(stencil) = stencil {
+                stencil.apply(&self.webgl_context);
+            }
+            for (mesh_data_id, transforms) in mesh_data_list {
+                let mesh_data_result = self.draw_meshes_using_mesh_data(
+                    mesh_data_id,
+                    material.clone(),
+                    transforms,
+                    instance_uniforms,
+                );
+                result = result.and(mesh_data_result);
+            }
+            if stencil.is_some() {
+                StencilConfig::reset(&self.webgl_context);
+            }
+            result
         } else {
-            console_error(&format!(
+            Err(Error::Load(format!(
                 "Meshes were not rendered because material {} is not registered.",
                 &material_id
-            ));
+            )))
         }
     }
 
+    /// Draws every mesh sharing a `MeshData` with `material`, whose shared uniforms are already set.  
+    /// Material instance uniforms are uploaded after them so that they override the parent's,
+    /// once for each run of meshes using the same instance: meshes are sorted by instance id.  
+    /// Without `instance_uniforms`, e.g. for depth materials, they are not uploaded at all.
     fn draw_meshes_using_mesh_data(
         &self,
         mesh_data_id: &usize,
         material: Rc<RefCell<Material>>,
-        mut transforms: Vec<(&usize, &Transform)>,
-    ) {
-        transforms.sort_by(|a, b| a.0.cmp(b.0));
-        let current_mat_instance_id = std::usize::MAX;
+        transforms: &[(&Mesh, &Transform)],
+        instance_uniforms: bool,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        let mut current_mat_instance_id = std::usize::MAX;
+        let mut overridden_names = Vec::new();
         if let Some(mesh_data) = self
             .asset_registry
             .get_mesh_data_with_index(mesh_data_id.to_owned())
         {
+            let mut used_attributes = Vec::new();
             for buffer in mesh_data.borrow().get_buffers() {
+                if material
+                    .borrow()
+                    .get_attribute_location(buffer.get_attribute_name())
+                    .is_none()
+                {
+                    material.borrow_mut().register_new_attribute_location(
+                        &self.webgl_context,
+                        buffer.get_attribute_name(),
+                    );
+                }
                 let location = material
                     .borrow()
                     .get_attribute_location(buffer.get_attribute_name());
                 if let Some(loc) = location {
                     buffer.enable_and_bind_attribute(&self.webgl_context, loc);
+                    if loc != -1 {
+                        used_attributes.push(loc as u32);
+                    }
                 } else {
                     console_error("Could not bind some buffers because locations were missing.");
                 }
             }
-            for (material_instance_id, transform) in transforms {
-                if material_instance_id != &current_mat_instance_id {
+            if material.borrow().uses_vertex_ao()
+                && mesh_data
+                    .borrow()
+                    .get_buffer(crate::utils::constants::AO_BUFFER_NAME)
+                    .is_none()
+            {
+                self.set_default_ao(material.clone());
+            }
+            self.disable_unused_attributes(used_attributes);
+            for (mesh, transform) in transforms {
+                let material_instance_id = mesh.get_material_instance_id();
+                if instance_uniforms && material_instance_id != &current_mat_instance_id {
                     if let Some(material_instance) = self
                         .asset_registry
                         .get_material_instance_with_index(material_instance_id.to_owned())
                     {
-                        material_instance
+                        let restore_result = material
                             .borrow()
-                            .set_uniforms_to_context(&self.webgl_context)
-                            .ok();
-                        self.set_transform_uniform(material.clone(), transform).ok();
-                        self.webgl_context.draw_elements_with_i32(
-                            WebGlRenderingContext::TRIANGLES,
-                            mesh_data.borrow().get_vertex_count(),
-                            WebGlRenderingContext::UNSIGNED_SHORT,
-                            0,
-                        );
+                            .set_uniforms_named_to_context(&self.webgl_context, &overridden_names);
+                        let instance_result = material_instance
+                            .borrow()
+                            .set_uniforms_to_context(&self.webgl_context);
+                        result = result.and(restore_result).and(instance_result);
+                        overridden_names = material_instance.borrow().get_uniform_names();
+                        current_mat_instance_id = *material_instance_id;
                     } else {
-                        console_error(&format!("Meshes were not rendered because material instance {} is not registered.",&material_instance_id));
+                        result = result.and(Err(Error::Load(format!("Meshes were not rendered because material instance {} is not registered.",&material_instance_id))));
+                        continue;
                     }
                 }
+                let transform_result = self.set_transform_uniform(material.clone(), transform);
+                result = result.and(transform_result);
+                let index_type = mesh_data.borrow().get_index_type();
+                let (index_count, offset) =
+                    get_draw_arguments(mesh, mesh_data.borrow().get_vertex_count(), index_type);
+                draw_in_depth_range(
+                    mesh.get_depth_range(),
+                    |near, far| self.webgl_context.depth_range(near, far),
+                    || {
+                        self.webgl_context.draw_elements_with_i32(
+                            WebGlRenderingContext::TRIANGLES,
+                            index_count,
+                            index_type,
+                            offset,
+                        )
+                    },
+                );
             }
+            result
         } else {
-            console_error(&format!(
+            Err(Error::Load(format!(
                 "Meshes were not rendered because mesh_data {} is not registered.",
                 &mesh_data_id
-            ));
+            )))
+        }
+    }
+
+    /// Binds a constant `1.0` (unoccluded) to the `a_ao` attribute of `material`, for meshes
+    /// without an ambient occlusion buffer. The attribute array is left disabled.
+    fn set_default_ao(&self, material: Rc<RefCell<Material>>) {
+        let name = crate::utils::constants::AO_BUFFER_NAME;
+        if material.borrow().get_attribute_location(name).is_none() {
+            material
+                .borrow_mut()
+                .register_new_attribute_location(&self.webgl_context, name);
+        }
+        if let Some(location) = material.borrow().get_attribute_location(name) {
+            if location != -1 {
+                self.webgl_context.vertex_attrib1f(location as u32, 1.0);
+            }
         }
     }
 
+    /// Disables the vertex attribute arrays enabled for a previous mesh that are not in
+    /// `used_attributes`, so that they cannot be read past the end of their buffers.
+    fn disable_unused_attributes(&self, used_attributes: Vec<u32>) {
+        let mut enabled_attributes = self.enabled_attributes.borrow_mut();
+        for location in enabled_attributes.iter() {
+            if !used_attributes.contains(location) {
+                self.webgl_context.disable_vertex_attrib_array(*location);
+            }
+        }
+        *enabled_attributes = used_attributes;
+    }
+
+    /// Returns a built-in material, like the error material used in place of materials
+    /// whose shaders failed to compile, compiling it on first use.
+    fn get_builtin_material(
+        &self,
+        builtin_material: &Rc<RefCell<Material>>,
+    ) -> Option<Rc<RefCell<Material>>> {
+        {
+            let mut material = builtin_material.borrow_mut();
+            if material.get_program().is_none() {
+                let light_config = LightConfiguration::default();
+                if let Err(message) = material.compile(&self.webgl_context, &light_config) {
+                    console_error(&format!(
+                        "Could not compile the built-in material {}: {}",
+                        material.get_id(),
+                        message
+                    ));
+                    return None;
+                }
+                material.lookup_locations(&self.webgl_context, &light_config);
+            }
+        }
+        Some(builtin_material.clone())
+    }
+
     /// Sets the global camera uniform for the whole scene  
     /// Meant to be used by `Self.render_objects`
-    fn set_camera_uniforms(&self, material: Rc<RefCell<Material>>) -> Result<(), String> {
+    fn set_camera_uniforms(&self, material: Rc<RefCell<Material>>) -> Result<(), Error> {
         let camera_view_uniform_location = material
             .borrow_mut()
             .global_uniform_locations
@@ -208,43 +1111,93 @@ impl Renderer {
             .global_uniform_locations
             .projection_matrix_location
             .clone();
+        let (view_matrix, camera_position, projection_matrix) =
+            get_camera_uniform_values(&self.main_camera.borrow(), self.camera_relative);
         let view_matrix_uniform = Uniform::new_with_location(
             crate::utils::constants::VIEW_MATRIX_NAME,
             camera_view_uniform_location,
-            Box::new(self.main_camera.borrow().get_view_matrix()),
+            Box::new(view_matrix),
         );
         let camera_position_uniform = Uniform::new_with_location(
             crate::utils::constants::CAMERA_POSITION_NAME,
             camera_position_uniform_location,
-            Box::new(self.main_camera.borrow().get_position().clone()),
+            Box::new(camera_position),
         );
         let projection_matrix_uniform = Uniform::new_with_location(
             crate::utils::constants::PROJECTION_MATRIX_NAME,
             camera_projection_uniform_location,
-            Box::new(self.main_camera.borrow().get_projection_matrix()),
+            Box::new(projection_matrix),
         );
         view_matrix_uniform.set_to_context(&self.webgl_context)?;
         camera_position_uniform.set_to_context(&self.webgl_context)?;
         projection_matrix_uniform.set_to_context(&self.webgl_context)
     }
 
+    /// Sets the elapsed time and delta time uniforms for the whole scene  
+    /// Meant to be used by `Self.render_objects`
+    fn set_time_uniforms(&self, material: Rc<RefCell<Material>>) -> Result<(), Error> {
+        let time_uniform_location = material
+            .borrow()
+            .global_uniform_locations
+            .time_location
+            .clone();
+        let delta_time_uniform_location = material
+            .borrow()
+            .global_uniform_locations
+            .delta_time_location
+            .clone();
+        let time_uniform = Uniform::new_with_location(
+            crate::utils::constants::TIME_NAME,
+            time_uniform_location,
+            Box::new(self.time),
+        );
+        let delta_time_uniform = Uniform::new_with_location(
+            crate::utils::constants::DELTA_TIME_NAME,
+            delta_time_uniform_location,
+            Box::new(self.delta_time),
+        );
+        time_uniform.set_to_context(&self.webgl_context)?;
+        delta_time_uniform.set_to_context(&self.webgl_context)
+    }
+
+    /// Sets the near fade uniform for transparent materials. See `get_near_fade_value`.  
+    /// Meant to be used by `Self.render_objects`
+    fn set_near_fade_uniform(
+        &self,
+        material: Rc<RefCell<Material>>,
+        near_fade: Vector2<f32>,
+    ) -> Result<(), Error> {
+        let near_fade_location = material
+            .borrow()
+            .global_uniform_locations
+            .near_fade_location
+            .clone();
+        let near_fade_uniform = Uniform::new_with_location(
+            crate::utils::constants::NEAR_FADE_NAME,
+            near_fade_location,
+            Box::new(near_fade),
+        );
+        near_fade_uniform.set_to_context(&self.webgl_context)
+    }
+
     /// Sets the world transform uniform for a specific object
     /// Meant to be used by `Self.render_objects`
     fn set_transform_uniform(
         &self,
         material: Rc<RefCell<Material>>,
         transform: &Transform,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let transfom_matrix_location = material
             .borrow_mut()
             .global_uniform_locations
             .world_transform_location
             .clone();
-        let world_matrix = transform.get_world_matrix();
+        let world_matrix =
+            get_relative_world_matrix(&transform.get_world_matrix(), &self.get_render_origin());
         let transform_uniform = Uniform::new_with_location(
             crate::utils::constants::WORLD_TRANSFORM_NAME,
             transfom_matrix_location,
-            Box::new(world_matrix.clone()),
+            Box::new(world_matrix),
         );
         transform_uniform.set_to_context(&self.webgl_context)
     }
@@ -255,9 +1208,19 @@ impl Renderer {
         &self,
         material: Rc<RefCell<Material>>,
         light_repository: &LightRepository,
-    ) -> Result<(), String> {
-        light_repository.set_material_uniforms(&self.webgl_context, material.clone());
-        Ok(())
+    ) -> Result<(), Error> {
+        light_repository.set_material_uniforms(
+            &self.webgl_context,
+            material,
+            &self.get_render_origin(),
+        )
+    }
+
+    /// Returns the number of shader program switches during the color passes of the last
+    /// rendered frame. Materials drawn with the same fallback program count once, and the
+    /// depth prepass is not counted.
+    pub fn get_material_switch_count(&self) -> u32 {
+        self.material_switch_count.get()
     }
 
     /// Getter for the asset registry, immutable version
@@ -270,7 +1233,8 @@ impl Renderer {
         &mut self,
         file_data: &[u8],
         file_type: FileType,
-    ) -> Result<String, String> {
+    ) -> Result<String, Error> {
+        self.set_dirty();
         match file_type {
             FileType::WMesh => self
                 .asset_registry
@@ -281,13 +1245,474 @@ impl Renderer {
     }
 
     /// Register an image for use as a texture by the Renderer, stored in the AssetRegistery
-    /// used by this Renderer.
+    /// used by this Renderer. The default `SamplerPolicy` is applied to it.
     pub fn register_texture(
         &mut self,
         image: &HtmlImageElement,
         id: String,
-    ) -> Result<String, String> {
+    ) -> Result<String, Error> {
+        let sampler = self.default_sampler;
+        self.register_texture_with_sampler(image, id, &sampler)
+    }
+
+    /// Register an image for use as a texture by the Renderer, overriding the default `SamplerPolicy`.
+    pub fn register_texture_with_sampler(
+        &mut self,
+        image: &HtmlImageElement,
+        id: String,
+        sampler: &SamplerPolicy,
+    ) -> Result<String, Error> {
+        self.set_dirty();
         self.asset_registry
-            .register_texture(&self.webgl_context, image, id)
+            .register_texture(&self.webgl_context, image, id, sampler)
+    }
+
+    /// Register raw 8-bit pixel data for use as a texture, using the default `SamplerPolicy`.
+    pub fn register_texture_from_pixels(
+        &mut self,
+        data: &[u8],
+        layout: PixelLayout,
+        id: String,
+    ) -> Result<String, Error> {
+        let sampler = self.default_sampler;
+        self.set_dirty();
+        self.asset_registry.register_texture_from_pixels(
+            &self.webgl_context,
+            data,
+            layout,
+            id,
+            &sampler,
+        )
+    }
+
+    /// Register raw 32-bit float pixel data for use as a texture, using the default `SamplerPolicy`.  
+    /// Requires the `OES_texture_float` extension. Filtering is nearest unless
+    /// `OES_texture_float_linear` is available.
+    pub fn register_float_texture_from_pixels(
+        &mut self,
+        data: &[f32],
+        layout: PixelLayout,
+        id: String,
+    ) -> Result<String, Error> {
+        let sampler = self.default_sampler;
+        self.set_dirty();
+        self.asset_registry.register_float_texture_from_pixels(
+            &self.webgl_context,
+            data,
+            layout,
+            id,
+            &sampler,
+        )
+    }
+
+    /// Sets the number of texture units a material and its instances may use together.  
+    /// Defaults to the device's `MAX_TEXTURE_IMAGE_UNITS`. Only affects materials
+    /// registered after this call.
+    pub fn set_max_texture_units(&mut self, max_texture_units: u32) {
+        self.asset_registry.set_max_texture_units(max_texture_units);
+    }
+
+    /// Reserves the last `reserved_texture_units` texture units for engine-internal textures.  
+    /// Materials are then limited to the remaining units. Only affects materials registered after this call.
+    pub fn set_reserved_texture_units(&mut self, reserved_texture_units: u32) {
+        self.asset_registry
+            .set_reserved_texture_units(reserved_texture_units);
+    }
+
+    /// Returns the number of texture units reserved for engine-internal textures.
+    pub fn get_reserved_texture_units(&self) -> u32 {
+        self.asset_registry.get_reserved_texture_units()
+    }
+
+    /// Sets the `SamplerPolicy` applied to textures registered without an explicit one.  
+    /// Only affects textures registered after this call.
+    pub fn set_default_sampler(&mut self, sampler: SamplerPolicy) {
+        self.default_sampler = sampler;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+
+    fn key(
+        material: usize,
+        mesh_data: usize,
+        textures: &[usize],
+        instance: usize,
+    ) -> MaterialSortKey {
+        MaterialSortKey {
+            program: (false, material),
+            mesh_data,
+            texture_set: textures.to_vec(),
+            material_instance: instance,
+        }
+    }
+
+    #[test]
+    fn meshes_sharing_a_program_are_adjacent() {
+        let ids: Vec<usize> = (0..4).collect();
+        let meshes: Vec<Mesh> = (0..4).map(|index| Mesh::new(0, index, 0)).collect();
+        let transform = Transform::new(
+            &Vector3::zeros(),
+            &Vector3::zeros(),
+            &Vector3::new(1., 1., 1.),
+        );
+        let sorted = sort_by_material_key(vec![
+            (key(2, 0, &[], 0), &ids[2], &ids[0], &meshes[0], &transform),
+            (key(1, 0, &[], 1), &ids[1], &ids[0], &meshes[1], &transform),
+            (key(2, 1, &[], 2), &ids[2], &ids[1], &meshes[2], &transform),
+            (key(1, 0, &[], 3), &ids[1], &ids[0], &meshes[3], &transform),
+        ]);
+        let materials: Vec<usize> = sorted.iter().map(|(material_id, _)| *material_id).collect();
+        assert_eq!(materials, vec![1, 2]);
+        assert_eq!(sorted[0].1.len(), 1);
+        assert_eq!(sorted[0].1[0].1.len(), 2);
+        assert_eq!(sorted[1].1.len(), 2);
+    }
+
+    #[test]
+    fn fallback_materials_are_drawn_last_and_together() {
+        let mut broken = key(0, 0, &[], 0);
+        broken.program.0 = true;
+        let mut other_broken = key(3, 0, &[], 0);
+        other_broken.program.0 = true;
+        let mut keys = [
+            broken.clone(),
+            key(2, 0, &[], 0),
+            other_broken.clone(),
+            key(1, 0, &[], 0),
+        ];
+        keys.sort();
+        assert_eq!(keys[2..], [broken, other_broken]);
+    }
+
+    #[test]
+    fn instances_sharing_textures_are_adjacent() {
+        let mut keys = [key(0, 0, &[5], 0), key(0, 0, &[7], 1), key(0, 0, &[5], 2)];
+        keys.sort();
+        let instances: Vec<usize> = keys.iter().map(|key| key.material_instance).collect();
+        assert_eq!(instances, vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn quality_steps_down_when_over_budget() {
+        let step = crate::utils::constants::ADAPTIVE_QUALITY_STEP;
+        assert_eq!(get_stepped_quality(1.0, 18.4, 16.0), 1.0 - step);
+        assert_eq!(get_stepped_quality(0.5, 32.0, 16.0), 0.5 - step);
+    }
+
+    #[test]
+    fn quality_steps_up_when_under_budget() {
+        let step = crate::utils::constants::ADAPTIVE_QUALITY_STEP;
+        assert_eq!(get_stepped_quality(0.5, 12.0, 16.0), 0.5 + step);
+        assert_eq!(get_stepped_quality(1.0, 4.0, 16.0), 1.0);
+    }
+
+    #[test]
+    fn quality_is_kept_within_the_dead_band() {
+        assert_eq!(get_stepped_quality(0.5, 13.0, 16.0), 0.5);
+        assert_eq!(get_stepped_quality(0.5, 16.0, 16.0), 0.5);
+        assert_eq!(get_stepped_quality(0.5, 17.5, 16.0), 0.5);
+    }
+
+    #[test]
+    fn quality_never_drops_below_the_minimum() {
+        let min = crate::utils::constants::MIN_ADAPTIVE_QUALITY;
+        assert_eq!(get_stepped_quality(min, 100.0, 16.0), min);
+    }
+
+    fn transform_at(z: f32) -> Transform {
+        let mut transform = Transform::new(
+            &Vector3::new(0.0, 0.0, z),
+            &Vector3::zeros(),
+            &Vector3::new(1.0, 1.0, 1.0),
+        );
+        transform.refresh_world_matrix(None);
+        transform
+    }
+
+    #[test]
+    fn farther_transparent_meshes_are_drawn_first() {
+        let (near_material, far_material, mesh_data) = (1, 2, 0);
+        let (near_mesh, far_mesh) = (Mesh::new(0, 0, 0), Mesh::new(0, 0, 0));
+        let (near_transform, far_transform) = (transform_at(2.0), transform_at(-10.0));
+        let materials = vec![
+            (
+                &near_material,
+                vec![(&mesh_data, vec![(&near_mesh, &near_transform)])],
+            ),
+            (
+                &far_material,
+                vec![(&mesh_data, vec![(&far_mesh, &far_transform)])],
+            ),
+        ];
+        let view_matrix = Camera::default().get_view_matrix();
+        let order: Vec<usize> = sort_back_to_front(&view_matrix, materials)
+            .into_iter()
+            .map(|(material_id, _)| material_id)
+            .collect();
+        assert_eq!(order, [far_material, near_material]);
+    }
+
+    #[test]
+    fn sort_bias_orders_transparent_meshes_at_the_same_depth() {
+        let (plain_material, biased_material, mesh_data) = (1, 2, 0);
+        let plain_mesh = Mesh::new(0, 0, 0);
+        let mut biased_mesh = Mesh::new(0, 0, 0);
+        biased_mesh.set_sort_bias(1.0);
+        let transform = transform_at(-5.0);
+        let materials = vec![
+            (
+                &plain_material,
+                vec![(&mesh_data, vec![(&plain_mesh, &transform)])],
+            ),
+            (
+                &biased_material,
+                vec![(&mesh_data, vec![(&biased_mesh, &transform)])],
+            ),
+        ];
+        let view_matrix = Camera::default().get_view_matrix();
+        let order: Vec<usize> = sort_back_to_front(&view_matrix, materials)
+            .into_iter()
+            .map(|(material_id, _)| material_id)
+            .collect();
+        assert_eq!(order, [biased_material, plain_material]);
+    }
+
+    #[test]
+    fn swapped_camera_changes_the_view_projection() {
+        let vp_matrix = |camera: &Camera| {
+            let (view, _, projection) = get_camera_uniform_values(camera, false);
+            projection * view
+        };
+        let editor_camera = Camera::default();
+        let game_camera = Camera::new(
+            1.0,
+            std::f32::consts::FRAC_PI_2,
+            0.1,
+            100.0,
+            &Point3::new(10.0, 0.0, 0.0),
+            &Point3::origin(),
+        );
+        assert_eq!(vp_matrix(&editor_camera), editor_camera.get_vp_matrix());
+        assert_eq!(vp_matrix(&game_camera), game_camera.get_vp_matrix());
+        assert_ne!(vp_matrix(&game_camera), vp_matrix(&editor_camera));
+    }
+
+    #[test]
+    fn camera_relative_uniforms_place_the_camera_at_the_origin() {
+        let camera = Camera::default();
+        let (view, position, _) = get_camera_uniform_values(&camera, true);
+        assert_eq!(position, Vector3::zeros());
+        assert_eq!(view, camera.get_view_rotation_matrix());
+    }
+
+    #[test]
+    fn absolute_uniforms_upload_the_eye_position() {
+        let camera = Camera::default();
+        let (_, position, _) = get_camera_uniform_values(&camera, false);
+        assert_eq!(position, camera.get_eye_position().coords);
+    }
+
+    #[test]
+    fn far_objects_keep_their_view_position_with_camera_relative_rendering() {
+        let eye = Point3::new(100_000.0, 20.0, -50_000.0);
+        let camera = Camera::new(
+            16. / 9.,
+            std::f32::consts::FRAC_PI_4,
+            1.0,
+            1000.0,
+            &eye,
+            &Point3::new(100_010.0, 20.0, -50_000.0),
+        );
+        let world_matrix = Matrix4::new_translation(&Vector3::new(100_005.0, 22.0, -50_003.0));
+        let (absolute_view, _, _) = get_camera_uniform_values(&camera, false);
+        let (relative_view, origin, _) = get_camera_uniform_values(&camera, true);
+        let relative_world = get_relative_world_matrix(&world_matrix, &eye.coords);
+        let absolute_position = (absolute_view * world_matrix).column(3).into_owned();
+        let relative_position = (relative_view * relative_world).column(3).into_owned();
+        assert_eq!(origin, Vector3::zeros());
+        assert!((absolute_position - relative_position).norm() < 0.05);
+        assert!(relative_world.column(3).xyz().norm() < 10.0);
+    }
+
+    #[test]
+    fn repeated_render_errors_are_reported_once() {
+        let missing_material = || Err(Error::Load(String::from("Material 3 is not registered.")));
+        let mut last_error = None;
+        assert!(get_new_error(&mut last_error, missing_material()).is_some());
+        assert!(get_new_error(&mut last_error, missing_material()).is_none());
+    }
+
+    #[test]
+    fn render_errors_are_reported_again_after_a_successful_frame() {
+        let missing_material = || Err(Error::Load(String::from("Material 3 is not registered.")));
+        let mut last_error = None;
+        assert!(get_new_error(&mut last_error, missing_material()).is_some());
+        assert!(get_new_error(&mut last_error, Ok(())).is_none());
+        assert_eq!(last_error, None);
+        assert!(get_new_error(&mut last_error, missing_material()).is_some());
+    }
+
+    #[test]
+    fn rendering_an_uncompiled_material_returns_an_error() {
+        let material = Material::new("void main() {}", "void main() {}", "broken");
+        match check_material_compiled(&material) {
+            Err(Error::ShaderCompile(message)) => assert!(message.contains("broken")),
+            other => panic!("Expected a shader compilation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn changed_render_errors_are_reported_again() {
+        let mut last_error = None;
+        let first = get_new_error(&mut last_error, Err(Error::Other(String::from("first"))));
+        let second = get_new_error(&mut last_error, Err(Error::Other(String::from("second"))));
+        assert!(first.unwrap().contains("first"));
+        assert!(second.unwrap().contains("second"));
+        assert!(get_new_error(&mut last_error, Err(Error::Other(String::from("first")))).is_some());
+    }
+
+    #[test]
+    fn depth_prepass_writes_depths_then_shades_equal_depths() {
+        let prepass = get_depth_state(true, true, false);
+        let color_pass = get_depth_state(true, false, false);
+        assert_eq!(prepass, (WebGlRenderingContext::LESS, true));
+        assert_eq!(color_pass, (WebGlRenderingContext::EQUAL, false));
+    }
+
+    #[test]
+    fn submeshes_sharing_a_mesh_data_draw_their_own_index_range() {
+        let mut first_submesh = Mesh::new(0, 0, 0);
+        assert!(first_submesh.set_draw_range(Some((0, 30)), 90).is_ok());
+        let mut second_submesh = Mesh::new(0, 0, 0);
+        assert!(second_submesh.set_draw_range(Some((30, 60)), 90).is_ok());
+        let short = WebGlRenderingContext::UNSIGNED_SHORT;
+        assert_eq!(get_draw_arguments(&first_submesh, 90, short), (30, 0));
+        assert_eq!(get_draw_arguments(&second_submesh, 90, short), (60, 60));
+        let int = WebGlRenderingContext::UNSIGNED_INT;
+        assert_eq!(get_draw_arguments(&second_submesh, 90, int), (60, 120));
+    }
+
+    #[test]
+    fn near_fade_is_uploaded_for_transparent_materials_only() {
+        assert_eq!(
+            get_near_fade_value(Some((5.0, 1.0)), true),
+            Some(Vector2::new(5.0, 1.0))
+        );
+        assert_eq!(get_near_fade_value(Some((5.0, 1.0)), false), None);
+        assert_eq!(
+            get_near_fade_value(None, true),
+            Some(Vector2::new(0.0, -1.0))
+        );
+    }
+
+    #[test]
+    fn invalid_near_fade_distances_are_uniform_errors() {
+        assert_eq!(check_near_fade(5.0, 1.0), Ok(()));
+        match check_near_fade(1.0, 5.0) {
+            Err(Error::Uniform(message)) => assert!(message.contains("near fade")),
+            other => panic!("Expected a uniform error, got {:?}", other),
+        }
+        assert!(check_near_fade(5.0, -1.0).is_err());
+    }
+
+    #[test]
+    fn lost_contexts_are_reported_as_such() {
+        assert_eq!(check_gl_error(WebGlRenderingContext::NO_ERROR), Ok(()));
+        assert_eq!(
+            check_gl_error(WebGlRenderingContext::CONTEXT_LOST_WEBGL),
+            Err(Error::ContextLost)
+        );
+        assert!(check_gl_error(WebGlRenderingContext::INVALID_OPERATION).is_err());
+    }
+
+    #[test]
+    fn custom_depth_ranges_are_restored_after_drawing() {
+        let calls = RefCell::new(Vec::new());
+        let set_depth_range = |near, far| calls.borrow_mut().push(Some((near, far)));
+        draw_in_depth_range(Some((0.0, 0.1)), set_depth_range, || {
+            calls.borrow_mut().push(None)
+        });
+        assert_eq!(*calls.borrow(), [Some((0.0, 0.1)), None, Some((0.0, 1.0))]);
+
+        calls.borrow_mut().clear();
+        draw_in_depth_range(None, set_depth_range, || calls.borrow_mut().push(None));
+        assert_eq!(*calls.borrow(), [None]);
+    }
+
+    #[test]
+    fn only_transparent_meshes_are_alpha_blended() {
+        assert_eq!(get_blend_func(false, false), None);
+        assert_eq!(
+            get_blend_func(false, true),
+            Some((
+                WebGlRenderingContext::SRC_ALPHA,
+                WebGlRenderingContext::ONE_MINUS_SRC_ALPHA
+            ))
+        );
+        let (_, transparent_depth_mask) = get_depth_state(false, false, true);
+        assert!(!transparent_depth_mask);
+    }
+
+    #[test]
+    fn overdraw_mode_blends_every_mesh_additively() {
+        let additive = Some((WebGlRenderingContext::ONE, WebGlRenderingContext::ONE));
+        assert_eq!(get_blend_func(true, false), additive);
+        assert_eq!(get_blend_func(true, true), additive);
+        let [_, (_, depth_tested)] = get_capability_states(true, true, true);
+        assert!(!depth_tested);
+    }
+
+    #[test]
+    fn cull_face_and_depth_test_toggle_their_capability() {
+        let enabled = |cull_face, depth_test| {
+            let [(_, culled), (_, depth_tested)] =
+                get_capability_states(cull_face, depth_test, false);
+            (culled, depth_tested)
+        };
+        assert_eq!(enabled(true, true), (true, true));
+        assert_eq!(enabled(false, true), (false, true));
+        assert_eq!(enabled(true, false), (true, false));
+        let [_, (_, depth_tested)] = get_capability_states(true, true, true);
+        assert!(!depth_tested);
+    }
+
+    #[test]
+    fn single_pass_opaque_meshes_write_depths() {
+        let state = get_depth_state(false, false, false);
+        assert_eq!(state, (WebGlRenderingContext::LESS, true));
+    }
+
+    #[test]
+    fn transparent_meshes_never_write_depths() {
+        for depth_prepass in [false, true].iter() {
+            let state = get_depth_state(*depth_prepass, false, true);
+            assert_eq!(state, (WebGlRenderingContext::LESS, false));
+        }
+    }
+
+    #[test]
+    fn frames_are_always_drawn_without_redraw_on_demand() {
+        assert!(get_needs_redraw(false, false));
+        assert!(get_needs_redraw(false, true));
+    }
+
+    #[test]
+    fn idle_frames_are_skipped_until_marked_dirty() {
+        let dirty = Cell::new(true);
+        let render = || {
+            let drawn = get_needs_redraw(true, dirty.get());
+            dirty.set(false);
+            drawn
+        };
+        assert!(render());
+        assert!(!render());
+        assert!(!render());
+        dirty.set(true);
+        assert!(render());
+        assert!(!render());
     }
 }
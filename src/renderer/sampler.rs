@@ -0,0 +1,208 @@
+//! Texture sampling settings applied to textures when they are registered.
+
+use web_sys::WebGlRenderingContext;
+
+/// `TEXTURE_MAX_ANISOTROPY_EXT` from the `EXT_texture_filter_anisotropic` extension
+const TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FE;
+
+/// `MAX_TEXTURE_MAX_ANISOTROPY_EXT` from the `EXT_texture_filter_anisotropic` extension
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
+
+/// ## SamplerPolicy
+///
+/// Filtering, wrapping and mipmapping settings for a texture.
+/// Filters and wrap modes are WebGL constants such as `WebGlRenderingContext::LINEAR`
+/// or `WebGlRenderingContext::CLAMP_TO_EDGE`.
+///
+/// ⚠️ On WebGL 1, mipmaps and `REPEAT` wrapping only work with power-of-two textures.
+#[derive(Clone, Copy)]
+pub struct SamplerPolicy {
+    /// Minification filter
+    pub min_filter: u32,
+
+    /// Magnification filter
+    pub mag_filter: u32,
+
+    /// Wrap mode along the horizontal texture coordinate
+    pub wrap_s: u32,
+
+    /// Wrap mode along the vertical texture coordinate
+    pub wrap_t: u32,
+
+    /// if `true`, mipmaps are generated when the texture is uploaded.
+    pub generate_mipmaps: bool,
+
+    /// Maximum anisotropy. Values above `1.0` are only applied if `EXT_texture_filter_anisotropic`
+    /// is available, and are clamped to the maximum supported value.
+    pub anisotropy: f32,
+}
+
+impl SamplerPolicy {
+    /// Trilinear filtering with generated mipmaps.
+    pub fn trilinear() -> SamplerPolicy {
+        SamplerPolicy {
+            min_filter: WebGlRenderingContext::LINEAR_MIPMAP_LINEAR,
+            mag_filter: WebGlRenderingContext::LINEAR,
+            generate_mipmaps: true,
+            ..Default::default()
+        }
+    }
+
+    /// Trilinear filtering with generated mipmaps and the given maximum anisotropy.
+    pub fn anisotropic(anisotropy: f32) -> SamplerPolicy {
+        SamplerPolicy {
+            anisotropy,
+            ..SamplerPolicy::trilinear()
+        }
+    }
+
+    /// Returns this policy adjusted so that a `width` by `height` texture is complete on WebGL 1:
+    /// non-power-of-two textures are clamped to the edge, without mipmaps.
+    pub fn for_size(&self, width: u32, height: u32) -> SamplerPolicy {
+        if width.is_power_of_two() && height.is_power_of_two() {
+            *self
+        } else {
+            SamplerPolicy {
+                min_filter: get_filter_without_mipmaps(self.min_filter),
+                wrap_s: WebGlRenderingContext::CLAMP_TO_EDGE,
+                wrap_t: WebGlRenderingContext::CLAMP_TO_EDGE,
+                generate_mipmaps: false,
+                ..*self
+            }
+        }
+    }
+
+    /// Returns this policy adjusted so that a float texture is complete: without mipmaps,
+    /// and with nearest filtering unless `linear_filtering` (`OES_texture_float_linear`) is available.
+    pub fn for_float_texture(&self, linear_filtering: bool) -> SamplerPolicy {
+        if linear_filtering {
+            SamplerPolicy {
+                min_filter: get_filter_without_mipmaps(self.min_filter),
+                generate_mipmaps: false,
+                ..*self
+            }
+        } else {
+            SamplerPolicy {
+                min_filter: WebGlRenderingContext::NEAREST,
+                mag_filter: WebGlRenderingContext::NEAREST,
+                generate_mipmaps: false,
+                ..*self
+            }
+        }
+    }
+
+    /// Applies this policy to the texture currently bound to `TEXTURE_2D`.
+    /// Must be called after the texture data has been uploaded for mipmaps to be generated.
+    pub fn apply(&self, context: &WebGlRenderingContext) {
+        context.tex_parameteri(
+            WebGlRenderingContext::TEXTURE_2D,
+            WebGlRenderingContext::TEXTURE_MIN_FILTER,
+            self.min_filter as i32,
+        );
+        context.tex_parameteri(
+            WebGlRenderingContext::TEXTURE_2D,
+            WebGlRenderingContext::TEXTURE_MAG_FILTER,
+            self.mag_filter as i32,
+        );
+        context.tex_parameteri(
+            WebGlRenderingContext::TEXTURE_2D,
+            WebGlRenderingContext::TEXTURE_WRAP_S,
+            self.wrap_s as i32,
+        );
+        context.tex_parameteri(
+            WebGlRenderingContext::TEXTURE_2D,
+            WebGlRenderingContext::TEXTURE_WRAP_T,
+            self.wrap_t as i32,
+        );
+        if self.generate_mipmaps {
+            context.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
+        }
+        if self.anisotropy > 1.0 {
+            if let Ok(Some(_)) = context.get_extension("EXT_texture_filter_anisotropic") {
+                let max_anisotropy = context
+                    .get_parameter(MAX_TEXTURE_MAX_ANISOTROPY_EXT)
+                    .ok()
+                    .and_then(|value| value.as_f64())
+                    .unwrap_or(1.0) as f32;
+                context.tex_parameterf(
+                    WebGlRenderingContext::TEXTURE_2D,
+                    TEXTURE_MAX_ANISOTROPY_EXT,
+                    self.anisotropy.min(max_anisotropy),
+                );
+            }
+        }
+    }
+}
+
+/// Returns the filter sampling the base level like `filter`, without using mipmaps.
+fn get_filter_without_mipmaps(filter: u32) -> u32 {
+    match filter {
+        WebGlRenderingContext::LINEAR_MIPMAP_LINEAR
+        | WebGlRenderingContext::LINEAR_MIPMAP_NEAREST => WebGlRenderingContext::LINEAR,
+        WebGlRenderingContext::NEAREST_MIPMAP_LINEAR
+        | WebGlRenderingContext::NEAREST_MIPMAP_NEAREST => WebGlRenderingContext::NEAREST,
+        _ => filter,
+    }
+}
+
+impl Default for SamplerPolicy {
+    /// Linear magnification, nearest minification, repeat wrapping and no mipmaps.
+    fn default() -> SamplerPolicy {
+        SamplerPolicy {
+            min_filter: WebGlRenderingContext::NEAREST,
+            mag_filter: WebGlRenderingContext::LINEAR,
+            wrap_s: WebGlRenderingContext::REPEAT,
+            wrap_t: WebGlRenderingContext::REPEAT,
+            generate_mipmaps: false,
+            anisotropy: 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trilinear_sampler_filters_between_mipmaps() {
+        let sampler = SamplerPolicy::trilinear();
+        assert_eq!(
+            sampler.min_filter,
+            WebGlRenderingContext::LINEAR_MIPMAP_LINEAR
+        );
+        assert_eq!(sampler.mag_filter, WebGlRenderingContext::LINEAR);
+        assert!(sampler.generate_mipmaps);
+    }
+
+    #[test]
+    fn power_of_two_textures_keep_their_sampler() {
+        let sampler = SamplerPolicy::trilinear().for_size(256, 64);
+        assert_eq!(sampler.wrap_s, WebGlRenderingContext::REPEAT);
+        assert_eq!(
+            sampler.min_filter,
+            WebGlRenderingContext::LINEAR_MIPMAP_LINEAR
+        );
+        assert!(sampler.generate_mipmaps);
+    }
+
+    #[test]
+    fn non_power_of_two_textures_are_clamped_without_mipmaps() {
+        let sampler = SamplerPolicy::trilinear().for_size(300, 64);
+        assert_eq!(sampler.wrap_s, WebGlRenderingContext::CLAMP_TO_EDGE);
+        assert_eq!(sampler.wrap_t, WebGlRenderingContext::CLAMP_TO_EDGE);
+        assert_eq!(sampler.min_filter, WebGlRenderingContext::LINEAR);
+        assert_eq!(sampler.mag_filter, WebGlRenderingContext::LINEAR);
+        assert!(!sampler.generate_mipmaps);
+    }
+
+    #[test]
+    fn float_textures_use_nearest_filtering_without_the_linear_extension() {
+        let sampler = SamplerPolicy::default().for_float_texture(false);
+        assert_eq!(sampler.min_filter, WebGlRenderingContext::NEAREST);
+        assert_eq!(sampler.mag_filter, WebGlRenderingContext::NEAREST);
+        let sampler = SamplerPolicy::trilinear().for_float_texture(true);
+        assert_eq!(sampler.min_filter, WebGlRenderingContext::LINEAR);
+        assert_eq!(sampler.mag_filter, WebGlRenderingContext::LINEAR);
+        assert!(!sampler.generate_mipmaps);
+    }
+}
@@ -0,0 +1,87 @@
+//! Stencil settings applied around the draws of a material.
+
+use web_sys::WebGlRenderingContext;
+
+/// ## StencilConfig
+///
+/// Stencil test and write settings for a `Material`, used for portals, mirrors and masks.
+/// Functions and operations are WebGL constants such as `WebGlRenderingContext::EQUAL`
+/// or `WebGlRenderingContext::REPLACE`.
+///
+/// ⚠️ The WebGL context must be created with `stencil: true` for the stencil test to have any effect,
+/// and the stencil buffer is only cleared each frame if `Renderer::set_clear_mask` includes `STENCIL_BUFFER_BIT`.
+#[derive(Clone, Copy)]
+pub struct StencilConfig {
+    /// Comparison function between `reference` and the stored stencil value
+    pub function: u32,
+
+    /// Reference value for the comparison and the `REPLACE` operation
+    pub reference: i32,
+
+    /// Mask applied to both `reference` and the stored value before comparing them
+    pub read_mask: u32,
+
+    /// Mask of the stencil bits that can be written
+    pub write_mask: u32,
+
+    /// Operation when the stencil test fails
+    pub fail: u32,
+
+    /// Operation when the stencil test passes but the depth test fails
+    pub depth_fail: u32,
+
+    /// Operation when both the stencil and depth tests pass
+    pub pass: u32,
+}
+
+impl StencilConfig {
+    /// Writes `reference` to the stencil buffer wherever the material is drawn,
+    /// marking a region such as the inside of a portal.
+    pub fn marking(reference: i32) -> StencilConfig {
+        StencilConfig {
+            reference,
+            pass: WebGlRenderingContext::REPLACE,
+            ..Default::default()
+        }
+    }
+
+    /// Only draws the material where the stencil buffer holds `reference`, without
+    /// modifying it. Used to render inside a region marked with `StencilConfig::marking`.
+    pub fn masked(reference: i32) -> StencilConfig {
+        StencilConfig {
+            function: WebGlRenderingContext::EQUAL,
+            reference,
+            write_mask: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Enables the stencil test with these settings.
+    pub fn apply(&self, context: &WebGlRenderingContext) {
+        context.enable(WebGlRenderingContext::STENCIL_TEST);
+        context.stencil_func(self.function, self.reference, self.read_mask);
+        context.stencil_op(self.fail, self.depth_fail, self.pass);
+        context.stencil_mask(self.write_mask);
+    }
+
+    /// Disables the stencil test and restores the default write mask.
+    pub fn reset(context: &WebGlRenderingContext) {
+        context.disable(WebGlRenderingContext::STENCIL_TEST);
+        context.stencil_mask(0xFF);
+    }
+}
+
+impl Default for StencilConfig {
+    /// Always passes and keeps the stored values.
+    fn default() -> StencilConfig {
+        StencilConfig {
+            function: WebGlRenderingContext::ALWAYS,
+            reference: 0,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+            fail: WebGlRenderingContext::KEEP,
+            depth_fail: WebGlRenderingContext::KEEP,
+            pass: WebGlRenderingContext::KEEP,
+        }
+    }
+}
@@ -15,6 +15,7 @@
 //!     - `Matrix3<f32>`
 //!     - `Matrix4<f32>`
 
+use crate::error::Error;
 use crate::renderer::LightConfiguration;
 use nalgebra::base::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
 use std::rc::Rc;
@@ -84,9 +85,14 @@ impl Uniform {
         }
     }
 
+    /// Forgets the location of this uniform, to look it up again in a new program.
+    pub fn reset_location(&mut self) {
+        self.location = None;
+    }
+
     /// Sets the uniform to the current WebGlContext (to be called at render time);  
     /// The appropriate WebGlProgram must have been set beforehand.
-    pub fn set_to_context(&self, context: &WebGlRenderingContext) -> Result<(), String> {
+    pub fn set_to_context(&self, context: &WebGlRenderingContext) -> Result<(), Error> {
         let result = self.value.set_to_context_at_location(
             context,
             if let Some(loc) = &self.location {
@@ -97,7 +103,7 @@ impl Uniform {
             self.texture_index,
         );
         if let Err(_) = result {
-            Err("Uniform couldn't be set".to_string())
+            Err(Error::Uniform(format!("{} couldn't be set", self.name)))
         } else {
             result
         }
@@ -113,7 +119,7 @@ pub trait UniformValue {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String>;
+    ) -> Result<(), Error>;
 }
 
 impl UniformValue for f32 {
@@ -122,7 +128,7 @@ impl UniformValue for f32 {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         context.uniform1fv_with_f32_array(location, slice::from_ref(self));
         Ok(())
     }
@@ -134,7 +140,7 @@ impl UniformValue for &[f32] {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Single, *self).set_to_context_at_location(
             context,
             location,
@@ -149,24 +155,14 @@ impl UniformValue for Rc<WebGlTexture> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         match texture_number {
-            None => Err(String::from(
+            None => Err(Error::Uniform(String::from(
                 "You must provide a texture number for Texture uniforms",
-            )),
+            ))),
             Some(number) => {
                 context.active_texture(get_texture_pointer(number));
                 context.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self));
-                context.tex_parameteri(
-                    WebGlRenderingContext::TEXTURE_2D,
-                    WebGlRenderingContext::TEXTURE_MAG_FILTER,
-                    WebGlRenderingContext::LINEAR as i32,
-                );
-                context.tex_parameteri(
-                    WebGlRenderingContext::TEXTURE_2D,
-                    WebGlRenderingContext::TEXTURE_MIN_FILTER,
-                    WebGlRenderingContext::NEAREST as i32,
-                );
                 context.uniform1i(location, number as i32);
                 Ok(())
             }
@@ -180,7 +176,7 @@ impl UniformValue for (ShaderDataType, &[f32]) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         match self.0 {
             ShaderDataType::Single => {
                 context.uniform1fv_with_f32_array(location, self.1);
@@ -210,7 +206,9 @@ impl UniformValue for (ShaderDataType, &[f32]) {
                 context.uniform_matrix4fv_with_f32_array(location, false, self.1);
                 Ok(())
             }
-            _ => Err(String::from("Invalid value supplied to uniform")),
+            _ => Err(Error::Uniform(String::from(
+                "Invalid value supplied to uniform",
+            ))),
         }
     }
 }
@@ -221,7 +219,7 @@ impl UniformValue for (ShaderDataType, Vec<f32>) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (self.0, self.1.as_slice()).set_to_context_at_location(context, location, texture_number)
     }
 }
@@ -232,7 +230,7 @@ impl UniformValue for i32 {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         context.uniform1iv_with_i32_array(location, slice::from_ref(self));
         Ok(())
     }
@@ -244,7 +242,7 @@ impl UniformValue for &[i32] {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Single, *self).set_to_context_at_location(
             context,
             location,
@@ -259,7 +257,7 @@ impl UniformValue for (ShaderDataType, &[i32]) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         match self.0 {
             ShaderDataType::Single => {
                 context.uniform1iv_with_i32_array(location, self.1);
@@ -277,7 +275,9 @@ impl UniformValue for (ShaderDataType, &[i32]) {
                 context.uniform4iv_with_i32_array(location, self.1);
                 Ok(())
             }
-            _ => Err(String::from("Invalid value supplied to uniform")),
+            _ => Err(Error::Uniform(String::from(
+                "Invalid value supplied to uniform",
+            ))),
         }
     }
 }
@@ -288,7 +288,7 @@ impl UniformValue for (ShaderDataType, &[i16]) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut new_vec = Vec::new();
         for i in self.1 {
             new_vec.push(*i as i32);
@@ -302,7 +302,7 @@ impl UniformValue for (ShaderDataType, Vec<i16>) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (self.0, self.1.as_slice()).set_to_context_at_location(context, location, texture_number)
     }
 }
@@ -313,7 +313,7 @@ impl UniformValue for (ShaderDataType, &[u8]) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut new_vec = Vec::new();
         for i in self.1 {
             new_vec.push(*i as i32);
@@ -327,7 +327,7 @@ impl UniformValue for (ShaderDataType, Vec<u8>) {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (self.0, self.1.as_slice()).set_to_context_at_location(context, location, texture_number)
     }
 }
@@ -338,7 +338,7 @@ impl UniformValue for Vector2<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Vector2, self.as_slice()).set_to_context_at_location(
             context,
             location,
@@ -353,7 +353,7 @@ impl UniformValue for &[Vector2<f32>] {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut vec: Vec<f32> = Vec::new();
         for vector in self.iter() {
             vec.splice(self.len()..self.len(), vector.as_slice().iter().cloned());
@@ -372,7 +372,7 @@ impl UniformValue for Vector3<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Vector3, self.as_slice()).set_to_context_at_location(
             context,
             location,
@@ -387,7 +387,7 @@ impl UniformValue for &[Vector3<f32>] {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut vec: Vec<f32> = Vec::new();
         for vector in self.iter() {
             vec.splice(self.len()..self.len(), vector.as_slice().iter().cloned());
@@ -406,7 +406,7 @@ impl UniformValue for Vector4<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Vector4, self.as_slice()).set_to_context_at_location(
             context,
             location,
@@ -421,7 +421,7 @@ impl UniformValue for &[Vector4<f32>] {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         let mut vec: Vec<f32> = Vec::new();
         for vector in self.iter() {
             vec.splice(self.len()..self.len(), vector.as_slice().iter().cloned());
@@ -437,7 +437,7 @@ impl UniformValue for Matrix2<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Matrix2, self.as_slice())
             .set_to_context_at_location(context, location, None)
     }
@@ -448,7 +448,7 @@ impl UniformValue for Matrix3<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Matrix3, self.as_slice())
             .set_to_context_at_location(context, location, None)
     }
@@ -459,7 +459,7 @@ impl UniformValue for Matrix4<f32> {
         context: &WebGlRenderingContext,
         location: Option<&WebGlUniformLocation>,
         _texture_number: Option<u32>,
-    ) -> Result<(), String> {
+    ) -> Result<(), Error> {
         (ShaderDataType::Matrix4, self.as_slice())
             .set_to_context_at_location(context, location, None)
     }
@@ -474,11 +474,19 @@ pub struct GlobalUniformLocations {
 
     pub world_transform_location: Option<WebGlUniformLocation>,
 
+    pub time_location: Option<WebGlUniformLocation>,
+
+    pub delta_time_location: Option<WebGlUniformLocation>,
+
+    pub near_fade_location: Option<WebGlUniformLocation>,
+
     pub ambiant_light_location: Option<WebGlUniformLocation>,
 
     pub point_lights_locations: Vec<LightUniformLocations>,
 
     pub directional_lights_locations: Vec<LightUniformLocations>,
+
+    pub tube_lights_locations: Vec<TubeLightUniformLocations>,
 }
 
 impl GlobalUniformLocations {
@@ -488,12 +496,17 @@ impl GlobalUniformLocations {
             camera_position_location: None,
             projection_matrix_location: None,
             world_transform_location: None,
+            time_location: None,
+            delta_time_location: None,
+            near_fade_location: None,
 
             ambiant_light_location: None,
 
             point_lights_locations: Default::default(),
 
             directional_lights_locations: Default::default(),
+
+            tube_lights_locations: Default::default(),
         }
     }
     pub fn lookup_locations(
@@ -519,6 +532,18 @@ impl GlobalUniformLocations {
             self.world_transform_location =
                 context.get_uniform_location(pg, crate::utils::constants::WORLD_TRANSFORM_NAME)
         }
+        if self.time_location == None {
+            self.time_location =
+                context.get_uniform_location(pg, crate::utils::constants::TIME_NAME)
+        }
+        if self.delta_time_location == None {
+            self.delta_time_location =
+                context.get_uniform_location(pg, crate::utils::constants::DELTA_TIME_NAME)
+        }
+        if self.near_fade_location == None {
+            self.near_fade_location =
+                context.get_uniform_location(pg, crate::utils::constants::NEAR_FADE_NAME)
+        }
 
         if self.ambiant_light_location == None {
             self.ambiant_light_location =
@@ -548,6 +573,13 @@ impl GlobalUniformLocations {
             );
             self.point_lights_locations.push(location);
         }
+
+        self.tube_lights_locations.clear();
+        for i in 0..light_config.tube {
+            let mut location: TubeLightUniformLocations = Default::default();
+            location.lookup_locations(i, context, pg);
+            self.tube_lights_locations.push(location);
+        }
     }
 }
 
@@ -629,16 +661,58 @@ impl LightUniformLocations {
     }
 }
 
-fn get_texture_pointer(texture_number: u32) -> u32 {
-    match texture_number {
-        0 => WebGlRenderingContext::TEXTURE0,
-        1 => WebGlRenderingContext::TEXTURE1,
-        2 => WebGlRenderingContext::TEXTURE2,
-        3 => WebGlRenderingContext::TEXTURE3,
-        4 => WebGlRenderingContext::TEXTURE4,
-        5 => WebGlRenderingContext::TEXTURE5,
-        6 => WebGlRenderingContext::TEXTURE6,
-        7 => WebGlRenderingContext::TEXTURE7,
-        _ => WebGlRenderingContext::TEXTURE8,
+/// Locations of a `TubeLight` GLSL struct: a `Light` positioned at the start of the tube,
+/// the end of the tube and its radius.
+#[derive(Default)]
+pub struct TubeLightUniformLocations {
+    pub light: LightUniformLocations,
+    pub end: Option<WebGlUniformLocation>,
+    pub radius: Option<WebGlUniformLocation>,
+}
+
+impl TubeLightUniformLocations {
+    pub fn lookup_locations(
+        &mut self,
+        light_index: usize,
+        context: &WebGlRenderingContext,
+        program: &WebGlProgram,
+    ) {
+        let light_type = format!(
+            "{}[{}]",
+            crate::utils::constants::TUBE_LIGHTS_NAME,
+            light_index
+        );
+        self.light.lookup_locations(
+            &format!(
+                "{}.{}",
+                light_type,
+                crate::utils::constants::TUBE_LIGHT_LIGHT_NAME
+            ),
+            None,
+            context,
+            program,
+        );
+        if self.end.is_none() {
+            self.end = LightUniformLocations::lookup_field_location(
+                &light_type,
+                crate::utils::constants::TUBE_LIGHT_END_NAME,
+                None,
+                context,
+                program,
+            );
+        }
+        if self.radius.is_none() {
+            self.radius = LightUniformLocations::lookup_field_location(
+                &light_type,
+                crate::utils::constants::TUBE_LIGHT_RADIUS_NAME,
+                None,
+                context,
+                program,
+            );
+        }
     }
 }
+
+fn get_texture_pointer(texture_number: u32) -> u32 {
+    WebGlRenderingContext::TEXTURE0 + texture_number
+}
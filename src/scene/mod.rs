@@ -9,10 +9,13 @@ use crate::component::*;
 use crate::renderer::{LightConfiguration, LightRepository, Renderer};
 use crate::system::{LightingSystem, RenderingSystem, SceneGraphSystem, ShaderCompilationSystem};
 use crate::utils::console_error;
-use crate::utils::{LightType, Vector3Data};
+use crate::utils::{LightType, PixelFormat, PixelLayout, Vector3Data};
+use js_sys::Date;
 use nalgebra::Vector3;
-use specs::{Builder, Entities, ReadStorage, RunNow, World, WorldExt, WriteStorage};
-use specs_hierarchy::HierarchySystem;
+use specs::{
+    Builder, Entities, Join, ReadExpect, ReadStorage, RunNow, World, WorldExt, WriteStorage,
+};
+use specs_hierarchy::{Hierarchy, HierarchySystem};
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -38,6 +41,12 @@ pub struct Scene {
     shader_compilation_system: Option<ShaderCompilationSystem>,
 
     rendering_system: Option<RenderingSystem>,
+
+    /// Timestamp of the first update, in milliseconds.
+    start_time: Option<f64>,
+
+    /// Timestamp of the previous update, in milliseconds.
+    last_update_time: f64,
 }
 
 #[wasm_bindgen]
@@ -62,6 +71,8 @@ impl Scene {
             lighting_system: LightingSystem {},
             shader_compilation_system: None,
             rendering_system: None,
+            start_time: None,
+            last_update_time: 0.0,
         };
 
         #[cfg(feature = "debug")]
@@ -133,47 +144,109 @@ impl Scene {
         entity.id()
     }
 
+    /// Creates a tube light entity: a segment of `length` centered on `position`, widened by `radius`,
+    /// for soft elongated lights. The tube lies along the entity's local X axis, and can be
+    /// oriented with `set_transform_rotation`.
+    pub fn create_tube_light_entity(
+        &mut self,
+        color: Vector3Data,
+        intensity: f32,
+        attenuation: f32,
+        position: Vector3Data,
+        length: f32,
+        radius: f32,
+    ) -> u32 {
+        let light = Light {
+            color: color.to_vector3(),
+            intensity: intensity,
+            attenuation: attenuation,
+        };
+        self.world
+            .create_entity()
+            .with(light)
+            .with(Tube {
+                length: length,
+                radius: radius,
+            })
+            .with(Transform::new(
+                &position.to_vector3(),
+                &Vector3::new(0.0, 0.0, 0.0),
+                &Vector3::new(1.0, 1.0, 1.0),
+            ))
+            .with(DirtyTransform)
+            .with(Enabled)
+            .build()
+            .id()
+    }
+
     pub fn create_mesh_entity(&mut self, mesh_data_id: &str, material_instance_id: &str) -> u32 {
-        if let Some(renderer_rc) = &self.main_renderer {
-            let renderer = renderer_rc.borrow();
-            let asset_registry = renderer.get_asset_registry();
-            let mesh_data_option = asset_registry.get_mesh_data(mesh_data_id);
-            let material_instance_option =
-                asset_registry.get_material_instance(material_instance_id);
-            if let (Some(_), Some(material_instance)) = (mesh_data_option, material_instance_option)
-            {
-                let parent_material = material_instance.borrow().get_parent().clone();
-                let mesh = Mesh::new(
-                    asset_registry.get_id_from_str(mesh_data_id).unwrap(),
-                    asset_registry
-                        .get_id_from_str(material_instance_id)
-                        .unwrap(),
-                    asset_registry
-                        .get_id_from_str(parent_material.borrow().get_id())
-                        .unwrap(),
-                );
-                let entity = self
-                    .world
-                    .create_entity()
-                    .with(mesh)
-                    .with(Transform::new(
-                        &Vector3::new(0., 0., 0.),
-                        &Vector3::new(0., 0., 0.),
-                        &Vector3::new(1., 1., 1.),
-                    ))
-                    .with(DirtyTransform)
-                    .with(Enabled)
-                    .build();
-                entity.id()
-            } else {
-                console_error("Provided material instance could not be found in registry. Did you forget to register it?");
-                u32::max_value()
-            }
+        if let Some(mesh) = self.make_mesh(mesh_data_id, material_instance_id) {
+            self.build_mesh_entity(mesh)
         } else {
             u32::max_value()
         }
     }
 
+    /// Creates `count` mesh entities sharing the same mesh data and material instance,
+    /// resolving the asset ids only once. Returns the ids of the created entities,
+    /// which is empty if the assets could not be found.
+    pub fn create_mesh_entities(
+        &mut self,
+        mesh_data_id: &str,
+        material_instance_id: &str,
+        count: u32,
+    ) -> Vec<u32> {
+        match self.make_mesh(mesh_data_id, material_instance_id) {
+            Some(mesh) => self.build_mesh_entities(&mesh, count),
+            None => Vec::new(),
+        }
+    }
+
+    /// Creates a mesh entity drawing only `index_count` indices of the `MeshData`,
+    /// starting at `first_index`, and parents it to `parent_id`.  
+    /// Use it to draw each material group of an imported model with its own material instance.
+    pub fn create_submesh_entity(
+        &mut self,
+        parent_id: u32,
+        mesh_data_id: &str,
+        material_instance_id: &str,
+        first_index: i32,
+        index_count: i32,
+    ) -> u32 {
+        let vertex_count = match &self.main_renderer {
+            None => return u32::max_value(),
+            Some(renderer) => match renderer
+                .borrow()
+                .get_asset_registry()
+                .get_mesh_data(mesh_data_id)
+            {
+                None => {
+                    console_error("Provided mesh data could not be found in registry. Did you forget to register it?");
+                    return u32::max_value();
+                }
+                Some(mesh_data) => mesh_data.borrow().get_vertex_count(),
+            },
+        };
+        if let Err(error) = check_draw_range(first_index, index_count, vertex_count) {
+            console_error(&error.to_string());
+            return u32::max_value();
+        }
+        let entity_id = self.create_mesh_entity(mesh_data_id, material_instance_id);
+        if entity_id == u32::max_value() {
+            return entity_id;
+        }
+        {
+            let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+            let entity = system_data.1.entity(entity_id);
+            if let Some(mesh) = system_data.0.get_mut(entity) {
+                mesh.set_draw_range(Some((first_index, index_count)), vertex_count)
+                    .ok();
+            }
+        }
+        self.set_parent(entity_id, parent_id);
+        entity_id
+    }
+
     pub fn set_transform_translation(&mut self, entity_id: u32, new_translation: Vector3Data) {
         let mut system_data: (
             WriteStorage<Transform>,
@@ -273,6 +346,151 @@ impl Scene {
         }
     }
 
+    /// Deletes an entity along with all its descendants in the scene graph,
+    /// so that their meshes and lights are no longer rendered.  
+    /// Like `get_children`, this relies on the hierarchy as of the last `update`.
+    pub fn remove_entity(&mut self, entity_id: u32) {
+        let entities_to_delete: Vec<_> = {
+            let system_data: (ReadExpect<Hierarchy<TransformParent>>, Entities) =
+                self.world.system_data();
+            let entity = system_data.1.entity(entity_id);
+            std::iter::once(entity)
+                .chain(system_data.0.all_children_iter(entity))
+                .collect()
+        };
+        if let Err(_) = self.world.delete_entities(&entities_to_delete) {
+            console_error("Could not delete the entity: it has already been deleted.");
+        }
+        self.set_dirty();
+    }
+
+    /// Detaches an entity from its parent, making it a root of the scene graph.  
+    /// Its local transform is kept and becomes relative to the world.
+    pub fn remove_parent(&mut self, entity_id: u32) {
+        let mut system_data: (
+            WriteStorage<TransformParent>,
+            Entities,
+            WriteStorage<DirtyTransform>,
+        ) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if system_data.0.remove(entity).is_none() {
+            console_error("Could not remove parent relationship: the entity has no parent.");
+            return;
+        }
+        if let Err(_) = system_data.2.insert(entity, DirtyTransform) {
+            console_error("Could not mark the entity as dirty");
+        }
+    }
+
+    /// Returns the ids of the direct children of an entity, in the order they were parented.  
+    /// The hierarchy is refreshed on `update`, so parenting changes since the last update are not reflected yet.
+    pub fn get_children(&self, entity_id: u32) -> Vec<u32> {
+        let system_data: (ReadExpect<Hierarchy<TransformParent>>, Entities) =
+            self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        system_data
+            .0
+            .children(entity)
+            .iter()
+            .map(|child| child.id())
+            .collect()
+    }
+
+    /// Parents `entity_id` to `parent_id` and places it at the given offset from its parent,
+    /// so that it rigidly follows the parent, e.g. for attachment points.
+    pub fn attach(
+        &mut self,
+        entity_id: u32,
+        parent_id: u32,
+        local_translation: Vector3Data,
+        local_rotation: Vector3Data,
+        local_scale: Vector3Data,
+    ) {
+        self.set_parent(entity_id, parent_id);
+        self.set_transform(entity_id, local_translation, local_rotation, local_scale);
+    }
+
+    /// Draws only `index_count` indices of a mesh entity's `MeshData`, starting at `first_index`.
+    /// See `Mesh::set_draw_range`.
+    pub fn set_mesh_draw_range(&mut self, entity_id: u32, first_index: i32, index_count: i32) {
+        let renderer = match &self.main_renderer {
+            None => return,
+            Some(renderer) => renderer.clone(),
+        };
+        let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if let Some(mesh) = system_data.0.get_mut(entity) {
+            let renderer = renderer.borrow();
+            let mesh_data_option = renderer
+                .get_asset_registry()
+                .get_mesh_data_with_index(*mesh.get_mesh_data_id());
+            if let Some(mesh_data) = mesh_data_option {
+                let vertex_count = mesh_data.borrow().get_vertex_count();
+                if let Err(error) =
+                    mesh.set_draw_range(Some((first_index, index_count)), vertex_count)
+                {
+                    console_error(&error.to_string());
+                }
+            }
+        } else {
+            console_error("Could not find mesh for entity.");
+        }
+        self.set_dirty();
+    }
+
+    /// Makes a mesh entity draw its whole `MeshData` again.
+    pub fn reset_mesh_draw_range(&mut self, entity_id: u32) {
+        let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if let Some(mesh) = system_data.0.get_mut(entity) {
+            mesh.set_draw_range(None, 0).ok();
+        } else {
+            console_error("Could not find mesh for entity.");
+        }
+        self.set_dirty();
+    }
+
+    /// Sets the transparency sort bias of a mesh entity. See `Mesh::set_sort_bias`.
+    pub fn set_mesh_sort_bias(&mut self, entity_id: u32, sort_bias: f32) {
+        let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if let Some(mesh) = system_data.0.get_mut(entity) {
+            mesh.set_sort_bias(sort_bias);
+        } else {
+            console_error("Could not find mesh for entity.");
+        }
+        self.set_dirty();
+    }
+
+    /// Draws a mesh entity into the `[near, far]` slice of the depth buffer.
+    /// See `Mesh::set_depth_range`.
+    pub fn set_mesh_depth_range(&mut self, entity_id: u32, near: f32, far: f32) {
+        if !(0.0 <= near && near <= far && far <= 1.0) {
+            console_error("Depth range must satisfy 0 <= near <= far <= 1.");
+            return;
+        }
+        let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if let Some(mesh) = system_data.0.get_mut(entity) {
+            mesh.set_depth_range(Some((near, far)));
+        } else {
+            console_error("Could not find mesh for entity.");
+        }
+        self.set_dirty();
+    }
+
+    /// Makes a mesh entity use the whole depth buffer again.
+    pub fn reset_mesh_depth_range(&mut self, entity_id: u32) {
+        let mut system_data: (WriteStorage<Mesh>, Entities) = self.world.system_data();
+        let entity = system_data.1.entity(entity_id);
+        if let Some(mesh) = system_data.0.get_mut(entity) {
+            mesh.set_depth_range(None);
+        } else {
+            console_error("Could not find mesh for entity.");
+        }
+        self.set_dirty();
+    }
+
     pub fn register_asset(&mut self, file_data: &[u8], file_type: FileType) -> String {
         match &mut self.main_renderer {
             None => {
@@ -280,8 +498,8 @@ impl Scene {
                 String::new()
             }
             Some(renderer) => match renderer.borrow_mut().register_asset(file_data, file_type) {
-                Err(message) => {
-                    console_error(&message);
+                Err(error) => {
+                    console_error(&error.to_string());
                     String::new()
                 }
                 Ok(id) => id,
@@ -297,8 +515,65 @@ impl Scene {
                 String::new()
             }
             Some(renderer) => match renderer.borrow_mut().register_texture(image, id) {
-                Err(message) => {
-                    console_error(&message);
+                Err(error) => {
+                    console_error(&error.to_string());
+                    String::new()
+                }
+                Ok(id) => id,
+            },
+        }
+    }
+
+    /// Registers raw 8-bit pixel data as a texture. `data` must hold exactly
+    /// `width * height` pixels in the given `format`.
+    pub fn register_texture_from_pixels(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        id: String,
+    ) -> String {
+        match &mut self.main_renderer {
+            None => {
+                console_error("Trying to register asset before initializing renderer!");
+                String::new()
+            }
+            Some(renderer) => match renderer.borrow_mut().register_texture_from_pixels(
+                data,
+                PixelLayout::new(width, height, format),
+                id,
+            ) {
+                Err(error) => {
+                    console_error(&error.to_string());
+                    String::new()
+                }
+                Ok(id) => id,
+            },
+        }
+    }
+
+    /// Registers raw 32-bit float pixel data as a texture. Requires the `OES_texture_float` extension.
+    pub fn register_float_texture_from_pixels(
+        &mut self,
+        data: &[f32],
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        id: String,
+    ) -> String {
+        match &mut self.main_renderer {
+            None => {
+                console_error("Trying to register asset before initializing renderer!");
+                String::new()
+            }
+            Some(renderer) => match renderer.borrow_mut().register_float_texture_from_pixels(
+                data,
+                PixelLayout::new(width, height, format),
+                id,
+            ) {
+                Err(error) => {
+                    console_error(&error.to_string());
                     String::new()
                 }
                 Ok(id) => id,
@@ -334,12 +609,21 @@ impl Scene {
 
     /// Function to be called each frame.
     pub fn update(&mut self) -> () {
+        let (time, delta_time) = self.tick_clock();
         if let (Some(renderer), Some(rendering_system), Some(shader_system)) = (
             &mut self.main_renderer,
             &mut self.rendering_system,
             &mut self.shader_compilation_system,
         ) {
             renderer.borrow_mut().resize_canvas();
+            renderer.borrow_mut().set_time(time, delta_time);
+            if (&self.world.read_storage::<DirtyTransform>())
+                .join()
+                .next()
+                .is_some()
+            {
+                renderer.borrow().set_dirty();
+            }
             self.hierarchy_system.run_now(&self.world);
             self.scene_graph_system.run_now(&self.world);
             self.lighting_system.run_now(&self.world);
@@ -353,6 +637,13 @@ impl Scene {
 }
 
 impl Scene {
+    /// Requests a new frame from the renderer, for changes it cannot detect by itself.
+    fn set_dirty(&self) {
+        if let Some(renderer) = &self.main_renderer {
+            renderer.borrow().set_dirty();
+        }
+    }
+
     /// Registers every common component for the current world.
     fn register_components(&mut self) -> () {
         self.world.register::<Transform>();
@@ -364,6 +655,17 @@ impl Scene {
         self.world.register::<Light>();
         self.world.register::<Direction>();
         self.world.register::<Cone>();
+        self.world.register::<Tube>();
+    }
+
+    /// Updates the internal clock and returns the elapsed time and the time since the
+    /// previous update, both in seconds.
+    fn tick_clock(&mut self) -> (f32, f32) {
+        let now = Date::now();
+        let start_time = *self.start_time.get_or_insert(now);
+        let times = get_clock_times(start_time, self.last_update_time, now);
+        self.last_update_time = now;
+        times
     }
 
     /// Instanciates and registers the resources for the current world.
@@ -374,6 +676,60 @@ impl Scene {
         self.world.insert(light_config);
     }
 
+    /// Resolves the asset ids of a mesh entity, logging an error if they are not registered.
+    fn make_mesh(&self, mesh_data_id: &str, material_instance_id: &str) -> Option<Mesh> {
+        let renderer = self.main_renderer.as_ref()?.borrow();
+        let asset_registry = renderer.get_asset_registry();
+        let mesh_data_option = asset_registry.get_mesh_data(mesh_data_id);
+        let material_instance_option = asset_registry.get_material_instance(material_instance_id);
+        if let (Some(_), Some(material_instance)) = (mesh_data_option, material_instance_option) {
+            let parent_material = material_instance.borrow().get_parent().clone();
+            let mesh = Mesh::new(
+                asset_registry.get_id_from_str(mesh_data_id).unwrap(),
+                asset_registry
+                    .get_id_from_str(material_instance_id)
+                    .unwrap(),
+                asset_registry
+                    .get_id_from_str(parent_material.borrow().get_id())
+                    .unwrap(),
+            );
+            Some(mesh)
+        } else {
+            console_error("Provided material instance could not be found in registry. Did you forget to register it?");
+            None
+        }
+    }
+
+    /// Creates an enabled entity for `mesh` with an identity transform.
+    fn build_mesh_entity(&mut self, mesh: Mesh) -> u32 {
+        let entity = self
+            .world
+            .create_entity()
+            .with(mesh)
+            .with(Transform::new(
+                &Vector3::new(0., 0., 0.),
+                &Vector3::new(0., 0., 0.),
+                &Vector3::new(1., 1., 1.),
+            ))
+            .with(DirtyTransform)
+            .with(Enabled)
+            .build();
+        entity.id()
+    }
+
+    /// Creates `count` mesh entities with the asset ids of `mesh`. Returns their ids.
+    fn build_mesh_entities(&mut self, mesh: &Mesh, count: u32) -> Vec<u32> {
+        (0..count)
+            .map(|_| {
+                self.build_mesh_entity(Mesh::new(
+                    *mesh.get_mesh_data_id(),
+                    *mesh.get_material_instance_id(),
+                    *mesh.get_material_id(),
+                ))
+            })
+            .collect()
+    }
+
     /// Gets a camera from the system storage and clones it to pass it to the renderer.  
     /// This might fail if an incorrect ID is given.
     fn get_camera_for_rendering(&self, camera_entity_id: u32) -> Result<Camera, String> {
@@ -386,3 +742,119 @@ impl Scene {
         }
     }
 }
+
+/// Returns the elapsed time since `start_time` and the time since `last_update_time`,
+/// in seconds, from timestamps in milliseconds.  
+/// A `last_update_time` of `0.0` means there was no previous update.
+fn get_clock_times(start_time: f64, last_update_time: f64, now: f64) -> (f32, f32) {
+    let delta_time = if last_update_time > 0.0 {
+        now - last_update_time
+    } else {
+        0.0
+    };
+    (
+        ((now - start_time) / 1000.0) as f32,
+        (delta_time / 1000.0) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_entity(scene: &mut Scene) -> u32 {
+        scene.build_mesh_entity(Mesh::new(0, 0, 0))
+    }
+
+    fn refresh_hierarchy(scene: &mut Scene) {
+        scene.world.maintain();
+        scene.hierarchy_system.run_now(&scene.world);
+    }
+
+    #[test]
+    fn children_are_listed_in_parenting_order() {
+        let mut scene = Scene::new();
+        let parent = new_entity(&mut scene);
+        let children: Vec<u32> = (0..3).map(|_| new_entity(&mut scene)).collect();
+        for child in &children {
+            scene.set_parent(*child, parent);
+        }
+        refresh_hierarchy(&mut scene);
+        assert_eq!(scene.get_children(parent), children);
+        assert!(scene.get_children(children[0]).is_empty());
+    }
+
+    #[test]
+    fn detaching_the_middle_child_keeps_its_siblings() {
+        let mut scene = Scene::new();
+        let parent = new_entity(&mut scene);
+        let children: Vec<u32> = (0..3).map(|_| new_entity(&mut scene)).collect();
+        for child in &children {
+            scene.set_parent(*child, parent);
+        }
+        refresh_hierarchy(&mut scene);
+        scene.remove_parent(children[1]);
+        refresh_hierarchy(&mut scene);
+        assert_eq!(scene.get_children(parent), [children[0], children[2]]);
+        let parents = scene.world.read_storage::<TransformParent>();
+        let entities = scene.world.entities();
+        assert!(parents.get(entities.entity(children[1])).is_none());
+    }
+
+    #[test]
+    fn removed_entities_take_their_descendants_out_of_rendering() {
+        let mut scene = Scene::new();
+        let (parent, child, grandchild, other) = (
+            new_entity(&mut scene),
+            new_entity(&mut scene),
+            new_entity(&mut scene),
+            new_entity(&mut scene),
+        );
+        scene.set_parent(child, parent);
+        scene.set_parent(grandchild, child);
+        refresh_hierarchy(&mut scene);
+        scene.remove_entity(parent);
+        scene.world.maintain();
+        let meshes = scene.world.read_storage::<Mesh>();
+        let entities = scene.world.entities();
+        let remaining: Vec<u32> = (&entities, &meshes)
+            .join()
+            .map(|(entity, _)| entity.id())
+            .collect();
+        assert_eq!(remaining, [other]);
+    }
+
+    #[test]
+    fn batch_created_meshes_match_single_creations() {
+        let mut scene = Scene::new();
+        let mesh = Mesh::new(4, 7, 2);
+        let single = scene.build_mesh_entity(Mesh::new(4, 7, 2));
+        let batch = scene.build_mesh_entities(&mesh, 3);
+        assert_eq!(batch.len(), 3);
+        let meshes = scene.world.read_storage::<Mesh>();
+        let transforms = scene.world.read_storage::<Transform>();
+        let entities = scene.world.entities();
+        let ids = |entity_id| {
+            let mesh = meshes.get(entities.entity(entity_id)).unwrap();
+            (
+                *mesh.get_mesh_data_id(),
+                *mesh.get_material_instance_id(),
+                *mesh.get_material_id(),
+            )
+        };
+        for entity_id in &batch {
+            assert_eq!(ids(*entity_id), ids(single));
+            assert!(transforms.get(entities.entity(*entity_id)).is_some());
+        }
+    }
+
+    #[test]
+    fn clock_times_are_in_elapsed_seconds() {
+        let start_time = 1_600_000_000_000.0;
+        assert_eq!(get_clock_times(start_time, 0.0, start_time), (0.0, 0.0));
+        assert_eq!(
+            get_clock_times(start_time, start_time + 1_500.0, start_time + 2_500.0),
+            (2.5, 1.0)
+        );
+    }
+}
@@ -1,8 +1,8 @@
 //! System for registering lights before rendering
 
-use crate::component::{Cone, Direction, Enabled, Light, Transform};
+use crate::component::{Cone, Direction, Enabled, Light, Transform, Tube};
 use crate::renderer::{LightConfiguration, LightRepository};
-use nalgebra::{Vector3, Vector4};
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
 use specs::{Entities, Join, ReadStorage, System, Write};
 
 pub struct LightingSystem;
@@ -14,6 +14,7 @@ impl<'a> System<'a> for LightingSystem {
         ReadStorage<'a, Transform>,
         ReadStorage<'a, Direction>,
         ReadStorage<'a, Cone>,
+        ReadStorage<'a, Tube>,
         ReadStorage<'a, Enabled>,
         Write<'a, LightRepository>,
         Write<'a, LightConfiguration>,
@@ -26,6 +27,7 @@ impl<'a> System<'a> for LightingSystem {
             transforms,
             directions,
             cones,
+            tubes,
             enableds,
             mut light_repository,
             mut light_configuration,
@@ -35,6 +37,7 @@ impl<'a> System<'a> for LightingSystem {
         light_repository.directional.clear();
         light_repository.point.clear();
         light_repository.spot.clear();
+        light_repository.tube.clear();
         let mut ambiant = Light {
             color: Vector3::new(0.0, 0.0, 0.0),
             intensity: 0.0,
@@ -45,7 +48,12 @@ impl<'a> System<'a> for LightingSystem {
             let direction_opt = directions.get(entity);
             let transform_opt = transforms.get(entity);
             let cone_opt = cones.get(entity);
-            if let (Some(direction), None) = (direction_opt, cone_opt) {
+            if let (Some(tube), Some(transform)) = (tubes.get(entity), transform_opt) {
+                let (start, end) = get_tube_ends(tube, &transform.get_world_matrix());
+                light_repository
+                    .tube
+                    .push((light.clone(), start, end, tube.clone()));
+            } else if let (Some(direction), None) = (direction_opt, cone_opt) {
                 light_repository
                     .directional
                     .push((light.clone(), direction.0));
@@ -81,5 +89,88 @@ impl<'a> System<'a> for LightingSystem {
         light_configuration.directional = light_repository.directional.len();
         light_configuration.point = light_repository.point.len();
         light_configuration.spot = light_repository.spot.len();
+        light_configuration.tube = light_repository.tube.len();
+    }
+}
+
+/// World positions of both ends of `tube`, which lies along the local X axis of `world_matrix`.
+fn get_tube_ends(tube: &Tube, world_matrix: &Matrix4<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let half_length = tube.length / 2.0;
+    (
+        world_matrix
+            .transform_point(&Point3::new(-half_length, 0.0, 0.0))
+            .coords,
+        world_matrix
+            .transform_point(&Point3::new(half_length, 0.0, 0.0))
+            .coords,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, RunNow, World, WorldExt};
+
+    #[test]
+    fn tube_ends_follow_the_transform() {
+        let tube = Tube {
+            length: 4.0,
+            radius: 0.5,
+        };
+        let mut transform = Transform::new(
+            &Vector3::new(1.0, 2.0, 3.0),
+            &Vector3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2),
+            &Vector3::new(1.0, 1.0, 1.0),
+        );
+        transform.refresh_world_matrix(None);
+        let (start, end) = get_tube_ends(&tube, &transform.get_world_matrix());
+        assert!((start - Vector3::new(1.0, 0.0, 3.0)).norm() < 1e-5);
+        assert!((end - Vector3::new(1.0, 4.0, 3.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn tube_lights_are_collected_in_their_own_bucket() {
+        let mut world = World::new();
+        world.register::<Light>();
+        world.register::<Transform>();
+        world.register::<Direction>();
+        world.register::<Cone>();
+        world.register::<Tube>();
+        world.register::<Enabled>();
+        world.insert(LightRepository::default());
+        world.insert(LightConfiguration::default());
+        let mut transform = Transform::new(
+            &Vector3::new(0.0, 5.0, 0.0),
+            &Vector3::zeros(),
+            &Vector3::new(1.0, 1.0, 1.0),
+        );
+        transform.refresh_world_matrix(None);
+        world
+            .create_entity()
+            .with(Light {
+                color: Vector3::new(1.0, 0.5, 0.25),
+                intensity: 2.0,
+                attenuation: 0.1,
+            })
+            .with(transform)
+            .with(Tube {
+                length: 2.0,
+                radius: 0.25,
+            })
+            .with(Enabled)
+            .build();
+
+        LightingSystem.run_now(&world);
+
+        let light_repository = world.read_resource::<LightRepository>();
+        assert!(light_repository.point.is_empty());
+        assert_eq!(light_repository.tube.len(), 1);
+        let (light, start, end, tube) = &light_repository.tube[0];
+        assert_eq!(light.intensity, 2.0);
+        assert_eq!(light.color, Vector3::new(1.0, 0.5, 0.25));
+        assert_eq!(*start, Vector3::new(-1.0, 5.0, 0.0));
+        assert_eq!(*end, Vector3::new(1.0, 5.0, 0.0));
+        assert_eq!(tube.radius, 0.25);
+        assert_eq!(world.read_resource::<LightConfiguration>().tube, 1);
     }
 }
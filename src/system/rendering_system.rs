@@ -28,21 +28,20 @@ impl<'a> System<'a> for RenderingSystem {
         for (mesh, transform, _) in (&mesh, &transform, &enabled).join() {
             let material_id = mesh.get_material_id();
             let mesh_data_id = mesh.get_mesh_data_id();
-            let mesh_instance_id = mesh.get_material_instance_id();
             if let Some(mesh_hash_map) = sorted_meshes.get_mut(material_id) {
                 if let Some(transform_vec) = mesh_hash_map.get_mut(mesh_data_id) {
-                    transform_vec.push((mesh_instance_id, &transform));
+                    transform_vec.push((mesh, &transform));
                 } else {
-                    mesh_hash_map.insert(mesh_data_id, vec![(mesh_instance_id, &transform)]);
+                    mesh_hash_map.insert(mesh_data_id, vec![(mesh, &transform)]);
                 }
             } else {
                 let mut mesh_hash_map = HashMap::new();
-                mesh_hash_map.insert(mesh_data_id, vec![(mesh_instance_id, transform)]);
+                mesh_hash_map.insert(mesh_data_id, vec![(mesh, transform)]);
                 sorted_meshes.insert(material_id, mesh_hash_map);
             }
         }
         self.renderer
             .borrow_mut()
-            .render_objects(sorted_meshes, &light_repository);
+            .render_objects_or_warn(sorted_meshes, &light_repository);
     }
 }
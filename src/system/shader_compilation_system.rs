@@ -20,7 +20,7 @@ impl<'a> System<'a> for ShaderCompilationSystem {
     fn run(&mut self, (mesh, light_config): Self::SystemData) {
         for mesh in (&mesh).join() {
             match mesh.compile_material(self.renderer.clone(), &light_config) {
-                Err(message) => console_error(&message),
+                Err(error) => console_error(&error.to_string()),
                 _ => {}
             }
         }
@@ -10,6 +10,21 @@ pub const PROJECTION_MATRIX_NAME: &str = "u_projection_matrix";
 /// Name for the world transform (model) matrix uniform
 pub const WORLD_TRANSFORM_NAME: &str = "u_world_transform";
 
+/// Name for the elapsed time uniform, in seconds since the first `Scene` update
+pub const TIME_NAME: &str = "u_time";
+
+/// Name for the frame delta time uniform, in seconds since the previous `Scene` update
+pub const DELTA_TIME_NAME: &str = "u_delta_time";
+
+/// Name for the near fade uniform of transparent materials, as `(start distance, end distance)`
+pub const NEAR_FADE_NAME: &str = "u_near_fade";
+
+/// Preprocessor symbol defined in fragment shaders of materials flipping back-face normals
+pub const FLIP_BACKFACE_NORMALS_DEFINE: &str = "FLIP_BACKFACE_NORMALS";
+
+/// Id of the shared fullscreen triangle `MeshData`
+pub const FULLSCREEN_TRIANGLE_ID: &str = "__fullscreen_triangle";
+
 /// Name for the ambiant light uniform
 pub const AMBIANT_LIGHT_NAME: &str = "u_ambiant_light";
 
@@ -19,6 +34,18 @@ pub const POINT_LIGHTS_NAME: &str = "u_point_lights";
 /// Name for the directional lights array uniform
 pub const DIRECTIONAL_LIGHTS_NAME: &str = "u_dir_lights";
 
+/// Name for the tube lights array uniform
+pub const TUBE_LIGHTS_NAME: &str = "u_tube_lights";
+
+/// Name for the Light field in the TubeLight GLSL struct, whose position is the start of the tube
+pub const TUBE_LIGHT_LIGHT_NAME: &str = "light";
+
+/// Name for the end position field in the TubeLight GLSL struct
+pub const TUBE_LIGHT_END_NAME: &str = "end";
+
+/// Name for the radius field in the TubeLight GLSL struct
+pub const TUBE_LIGHT_RADIUS_NAME: &str = "radius";
+
 /// Name for the color field in the Light GLSL struct
 pub const LIGHT_COLOR_NAME: &str = "color";
 
@@ -39,3 +66,74 @@ pub const NORMAL_BUFFER_NAME: &str = "a_normal";
 
 /// UV (texture coordinates) buffer name used in shaders
 pub const UV_BUFFER_NAME: &str = "a_tex_coordinates";
+
+/// Baked per-vertex ambient occlusion buffer name used in shaders (scalar, `1.0` meaning unoccluded)
+pub const AO_BUFFER_NAME: &str = "a_ao";
+
+/// Preprocessor symbol defined in both shaders of materials reading baked ambient occlusion
+pub const USE_AO_DEFINE: &str = "USE_AO";
+
+/// Id of the built-in material substituted for materials that failed to compile
+pub const ERROR_MATERIAL_ID: &str = "wtvr3d_error_material";
+
+/// Vertex shader of the built-in error material
+pub const ERROR_VERTEX_SHADER: &str = "
+attribute vec4 a_position;
+
+uniform mat4 u_view_matrix;
+uniform mat4 u_projection_matrix;
+uniform mat4 u_world_transform;
+
+void main() {
+    gl_Position = u_projection_matrix * u_view_matrix * u_world_transform * a_position;
+}
+";
+
+/// Fragment shader of the built-in error material, rendering everything in magenta
+pub const ERROR_FRAGMENT_SHADER: &str = "
+precision mediump float;
+
+void main() {
+    gl_FragColor = vec4(1.0, 0.0, 1.0, 1.0);
+}
+";
+
+/// Id of the built-in material used to visualize overdraw
+pub const OVERDRAW_MATERIAL_ID: &str = "wtvr3d_overdraw_material";
+
+/// Declaration injected in every vertex shader, so that the depth prepass and color pass
+/// programs compute identical depths
+pub const INVARIANT_POSITION_DECLARATION: &str = "invariant gl_Position;";
+
+/// Fragment shader of the built-in overdraw material, adding a small constant to each
+/// pixel every time it is drawn
+pub const OVERDRAW_FRAGMENT_SHADER: &str = "
+precision mediump float;
+
+void main() {
+    gl_FragColor = vec4(0.1, 0.1, 0.1, 1.0);
+}
+";
+
+/// Suffix appended to a material's id to name its depth prepass material
+pub const DEPTH_MATERIAL_SUFFIX: &str = "__depth";
+
+/// Fragment shader of depth prepass materials. Color writes are disabled during the
+/// prepass, so it only needs to be as cheap as possible.
+pub const DEPTH_FRAGMENT_SHADER: &str = "
+precision lowp float;
+
+void main() {
+    gl_FragColor = vec4(0.0);
+}
+";
+
+/// Lowest resolution scale the adaptive quality controller can pick
+pub const MIN_ADAPTIVE_QUALITY: f32 = 0.25;
+
+/// Resolution scale change applied by the adaptive quality controller at each adjustment
+pub const ADAPTIVE_QUALITY_STEP: f32 = 0.05;
+
+/// Texture units assumed to be available until the device is queried.
+/// This is the minimum `MAX_TEXTURE_IMAGE_UNITS` guaranteed by WebGL 1.
+pub const DEFAULT_MAX_TEXTURE_UNITS: u32 = 8;
@@ -1,24 +1,91 @@
 //! Useful miscelaneous functions
 
 pub mod constants;
+pub mod noise;
 mod transfer_types;
 
-pub use transfer_types::{LightType, Vector3Data};
+pub use transfer_types::{LightType, PixelFormat, PixelLayout, Vector3Data};
 
+#[cfg(not(test))]
 use wasm_bindgen::JsValue;
+#[cfg(not(test))]
 use web_sys::console::{error_1, log_1, warn_1};
 
 /// Logs to the console with `log` level.
+#[cfg(not(test))]
 pub fn console_log(message: &str) {
     log_1(&JsValue::from_str(message));
 }
 
 /// Logs to the console with `warn` level.
+#[cfg(not(test))]
 pub fn console_warn(message: &str) {
     warn_1(&JsValue::from_str(message));
 }
 
 /// Logs to the console with `error` level.
+#[cfg(not(test))]
 pub fn console_error(message: &str) {
     error_1(&JsValue::from_str(message));
 }
+
+/// Native unit tests cannot call into JS: messages go to the standard error output instead.
+#[cfg(test)]
+pub fn console_log(message: &str) {
+    eprintln!("{}", message);
+}
+
+#[cfg(test)]
+pub fn console_warn(message: &str) {
+    eprintln!("{}", message);
+}
+
+#[cfg(test)]
+pub fn console_error(message: &str) {
+    eprintln!("{}", message);
+}
+
+/// Formats a float as a GLSL float literal, for values substituted into shader sources.  
+/// Unlike `{}`, the result always has a decimal point or an exponent (`2.0`, not `2`),
+/// and keeps enough digits to round-trip. Infinities are clamped to `±f32::MAX` and NaN becomes `0.0`.
+pub fn format_glsl_float(value: f32) -> String {
+    if value.is_nan() {
+        String::from("0.0")
+    } else if value.is_infinite() {
+        format!("{:?}", value.signum() * f32::MAX)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glsl_floats_always_have_a_decimal_point() {
+        assert_eq!(format_glsl_float(1.0), "1.0");
+        assert_eq!(format_glsl_float(0.5), "0.5");
+        assert_eq!(format_glsl_float(100.0), "100.0");
+        assert_eq!(format_glsl_float(-2.0), "-2.0");
+    }
+
+    #[test]
+    fn glsl_floats_round_trip() {
+        let value = 0.1 + 0.2;
+        assert_eq!(format_glsl_float(value).parse::<f32>().unwrap(), value);
+    }
+
+    #[test]
+    fn non_finite_glsl_floats_are_replaced() {
+        assert_eq!(format_glsl_float(f32::NAN), "0.0");
+        assert_eq!(
+            format_glsl_float(f32::INFINITY).parse::<f32>().unwrap(),
+            f32::MAX
+        );
+        assert_eq!(
+            format_glsl_float(f32::NEG_INFINITY).parse::<f32>().unwrap(),
+            -f32::MAX
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Deterministic, seed-based gradient noise functions.
+//!
+//! Gradients are picked by hashing the lattice coordinates with the seed instead of
+//! using a permutation table, so every function is allocation-free and returns the
+//! same value for the same seed and coordinates on every platform.
+//! All functions return values in the `[-1.0, 1.0]` range.
+
+/// Skewing factor for 2D simplex noise, `(sqrt(3) - 1) / 2`
+const SKEW_2D: f32 = 0.366_025_42;
+
+/// Unskewing factor for 2D simplex noise, `(3 - sqrt(3)) / 6`
+const UNSKEW_2D: f32 = 0.211_324_87;
+
+/// Skewing factor for 3D simplex noise
+const SKEW_3D: f32 = 1.0 / 3.0;
+
+/// Unskewing factor for 3D simplex noise
+const UNSKEW_3D: f32 = 1.0 / 6.0;
+
+/// Classic 2D Perlin noise at `(x, y)` for the given `seed`, in `[-1.0, 1.0]`.
+pub fn perlin2(seed: u32, x: f32, y: f32) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let (xi, yi) = (x0 as i32, y0 as i32);
+    let (xf, yf) = (x - x0, y - y0);
+    let u = fade(xf);
+    let v = fade(yf);
+
+    let n00 = gradient2(hash2(seed, xi, yi), xf, yf);
+    let n10 = gradient2(hash2(seed, xi + 1, yi), xf - 1.0, yf);
+    let n01 = gradient2(hash2(seed, xi, yi + 1), xf, yf - 1.0);
+    let n11 = gradient2(hash2(seed, xi + 1, yi + 1), xf - 1.0, yf - 1.0);
+
+    let result = lerp(lerp(n00, n10, u), lerp(n01, n11, u), v);
+    clamp_unit(result)
+}
+
+/// Classic 3D Perlin noise at `(x, y, z)` for the given `seed`, in `[-1.0, 1.0]`.
+pub fn perlin3(seed: u32, x: f32, y: f32, z: f32) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let z0 = z.floor();
+    let (xi, yi, zi) = (x0 as i32, y0 as i32, z0 as i32);
+    let (xf, yf, zf) = (x - x0, y - y0, z - z0);
+    let u = fade(xf);
+    let v = fade(yf);
+    let w = fade(zf);
+
+    let n000 = gradient3(hash3(seed, xi, yi, zi), xf, yf, zf);
+    let n100 = gradient3(hash3(seed, xi + 1, yi, zi), xf - 1.0, yf, zf);
+    let n010 = gradient3(hash3(seed, xi, yi + 1, zi), xf, yf - 1.0, zf);
+    let n110 = gradient3(hash3(seed, xi + 1, yi + 1, zi), xf - 1.0, yf - 1.0, zf);
+    let n001 = gradient3(hash3(seed, xi, yi, zi + 1), xf, yf, zf - 1.0);
+    let n101 = gradient3(hash3(seed, xi + 1, yi, zi + 1), xf - 1.0, yf, zf - 1.0);
+    let n011 = gradient3(hash3(seed, xi, yi + 1, zi + 1), xf, yf - 1.0, zf - 1.0);
+    let n111 = gradient3(
+        hash3(seed, xi + 1, yi + 1, zi + 1),
+        xf - 1.0,
+        yf - 1.0,
+        zf - 1.0,
+    );
+
+    let front = lerp(lerp(n000, n100, u), lerp(n010, n110, u), v);
+    let back = lerp(lerp(n001, n101, u), lerp(n011, n111, u), v);
+    clamp_unit(lerp(front, back, w))
+}
+
+/// 2D simplex noise at `(x, y)` for the given `seed`, in `[-1.0, 1.0]`.
+/// Cheaper than `perlin2` and without its axis-aligned artifacts.
+pub fn simplex2(seed: u32, x: f32, y: f32) -> f32 {
+    let skew = (x + y) * SKEW_2D;
+    let i = (x + skew).floor();
+    let j = (y + skew).floor();
+    let unskew = (i + j) * UNSKEW_2D;
+    let x0 = x - (i - unskew);
+    let y0 = y - (j - unskew);
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let x1 = x0 - i1 as f32 + UNSKEW_2D;
+    let y1 = y0 - j1 as f32 + UNSKEW_2D;
+    let x2 = x0 - 1.0 + 2.0 * UNSKEW_2D;
+    let y2 = y0 - 1.0 + 2.0 * UNSKEW_2D;
+    let (ii, jj) = (i as i32, j as i32);
+
+    let n0 = simplex_corner2(hash2(seed, ii, jj), x0, y0);
+    let n1 = simplex_corner2(hash2(seed, ii + i1, jj + j1), x1, y1);
+    let n2 = simplex_corner2(hash2(seed, ii + 1, jj + 1), x2, y2);
+    clamp_unit(70.0 * (n0 + n1 + n2))
+}
+
+/// 3D simplex noise at `(x, y, z)` for the given `seed`, in `[-1.0, 1.0]`.
+/// Cheaper than `perlin3` and without its axis-aligned artifacts.
+pub fn simplex3(seed: u32, x: f32, y: f32, z: f32) -> f32 {
+    let skew = (x + y + z) * SKEW_3D;
+    let i = (x + skew).floor();
+    let j = (y + skew).floor();
+    let k = (z + skew).floor();
+    let unskew = (i + j + k) * UNSKEW_3D;
+    let x0 = x - (i - unskew);
+    let y0 = y - (j - unskew);
+    let z0 = z - (k - unskew);
+
+    // Offsets of the second and third corners of the simplex containing the point
+    let ((i1, j1, k1), (i2, j2, k2)) = if x0 >= y0 {
+        if y0 >= z0 {
+            ((1, 0, 0), (1, 1, 0))
+        } else if x0 >= z0 {
+            ((1, 0, 0), (1, 0, 1))
+        } else {
+            ((0, 0, 1), (1, 0, 1))
+        }
+    } else if y0 < z0 {
+        ((0, 0, 1), (0, 1, 1))
+    } else if x0 < z0 {
+        ((0, 1, 0), (0, 1, 1))
+    } else {
+        ((0, 1, 0), (1, 1, 0))
+    };
+
+    let x1 = x0 - i1 as f32 + UNSKEW_3D;
+    let y1 = y0 - j1 as f32 + UNSKEW_3D;
+    let z1 = z0 - k1 as f32 + UNSKEW_3D;
+    let x2 = x0 - i2 as f32 + 2.0 * UNSKEW_3D;
+    let y2 = y0 - j2 as f32 + 2.0 * UNSKEW_3D;
+    let z2 = z0 - k2 as f32 + 2.0 * UNSKEW_3D;
+    let x3 = x0 - 1.0 + 3.0 * UNSKEW_3D;
+    let y3 = y0 - 1.0 + 3.0 * UNSKEW_3D;
+    let z3 = z0 - 1.0 + 3.0 * UNSKEW_3D;
+    let (ii, jj, kk) = (i as i32, j as i32, k as i32);
+
+    let n0 = simplex_corner3(hash3(seed, ii, jj, kk), x0, y0, z0);
+    let n1 = simplex_corner3(hash3(seed, ii + i1, jj + j1, kk + k1), x1, y1, z1);
+    let n2 = simplex_corner3(hash3(seed, ii + i2, jj + j2, kk + k2), x2, y2, z2);
+    let n3 = simplex_corner3(hash3(seed, ii + 1, jj + 1, kk + 1), x3, y3, z3);
+    clamp_unit(32.0 * (n0 + n1 + n2 + n3))
+}
+
+/// Contribution of a single 2D simplex corner
+fn simplex_corner2(hash: u32, x: f32, y: f32) -> f32 {
+    let t = 0.5 - x * x - y * y;
+    if t < 0.0 {
+        0.0
+    } else {
+        let t2 = t * t;
+        t2 * t2 * gradient2(hash, x, y)
+    }
+}
+
+/// Contribution of a single 3D simplex corner
+fn simplex_corner3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let t = 0.6 - x * x - y * y - z * z;
+    if t < 0.0 {
+        0.0
+    } else {
+        let t2 = t * t;
+        t2 * t2 * gradient3(hash, x, y, z)
+    }
+}
+
+/// Dot product of `(x, y)` with one of 8 gradient directions picked by `hash`
+fn gradient2(hash: u32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// Dot product of `(x, y, z)` with one of Ken Perlin's 12 gradient directions picked by `hash`
+fn gradient3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+/// Hashes 2D lattice coordinates with `seed`
+fn hash2(seed: u32, x: i32, y: i32) -> u32 {
+    mix(seed ^ mix((x as u32).wrapping_mul(0x27d4_eb2d) ^ mix(y as u32)))
+}
+
+/// Hashes 3D lattice coordinates with `seed`
+fn hash3(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    hash2(mix(seed ^ (z as u32).wrapping_mul(0x1656_67b1)), x, y)
+}
+
+/// Integer finalizer scrambling the bits of `value`
+fn mix(mut value: u32) -> u32 {
+    value ^= value >> 16;
+    value = value.wrapping_mul(0x7feb_352d);
+    value ^= value >> 15;
+    value = value.wrapping_mul(0x846c_a68b);
+    value ^ (value >> 16)
+}
+
+/// Quintic interpolation curve `6t^5 - 15t^4 + 10t^3`
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn clamp_unit(value: f32) -> f32 {
+    value.clamp(-1.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample coordinates spread over several lattice cells, including negative ones
+    fn sample_points() -> Vec<(f32, f32, f32)> {
+        let mut points = Vec::new();
+        for i in -20..20 {
+            let t = i as f32 * 0.37;
+            points.push((t, t * 1.7 - 3.1, 5.3 - t * 0.9));
+        }
+        points
+    }
+
+    #[test]
+    fn same_seed_and_coordinates_give_same_value() {
+        for (x, y, z) in sample_points() {
+            assert_eq!(perlin2(42, x, y), perlin2(42, x, y));
+            assert_eq!(perlin3(42, x, y, z), perlin3(42, x, y, z));
+            assert_eq!(simplex2(42, x, y), simplex2(42, x, y));
+            assert_eq!(simplex3(42, x, y, z), simplex3(42, x, y, z));
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_noise() {
+        let differs = sample_points()
+            .into_iter()
+            .any(|(x, y, z)| perlin3(1, x, y, z) != perlin3(2, x, y, z));
+        assert!(differs);
+    }
+
+    #[test]
+    fn values_stay_in_unit_range() {
+        for seed in 0..8 {
+            for (x, y, z) in sample_points() {
+                for value in &[
+                    perlin2(seed, x, y),
+                    perlin3(seed, x, y, z),
+                    simplex2(seed, x, y),
+                    simplex3(seed, x, y, z),
+                ] {
+                    assert!(*value >= -1.0 && *value <= 1.0, "{} is out of range", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn perlin_noise_is_zero_on_lattice_points() {
+        assert_eq!(perlin2(7, 3.0, -2.0), 0.0);
+        assert_eq!(perlin3(7, 3.0, -2.0, 1.0), 0.0);
+    }
+}
@@ -1,6 +1,8 @@
+use crate::error::Error;
 use nalgebra::{Point3, Vector3};
 /// Defines a few transfer types to facilitate communciation between JS world and WASM world.
 use wasm_bindgen::prelude::*;
+use web_sys::WebGlRenderingContext;
 
 /// Simple transfer type for Vector3 since it is not `wasm-bindgen` compatible.
 #[wasm_bindgen]
@@ -37,6 +39,30 @@ impl Vector3Data {
     }
 }
 
+impl From<Vector3<f32>> for Vector3Data {
+    fn from(vector: Vector3<f32>) -> Vector3Data {
+        Vector3Data::new(vector.x, vector.y, vector.z)
+    }
+}
+
+impl From<Vector3Data> for Vector3<f32> {
+    fn from(data: Vector3Data) -> Vector3<f32> {
+        data.to_vector3()
+    }
+}
+
+impl From<Point3<f32>> for Vector3Data {
+    fn from(point: Point3<f32>) -> Vector3Data {
+        Vector3Data::new(point.x, point.y, point.z)
+    }
+}
+
+impl From<Vector3Data> for Point3<f32> {
+    fn from(data: Vector3Data) -> Point3<f32> {
+        data.to_point3()
+    }
+}
+
 #[wasm_bindgen]
 #[derive(Clone, Copy)]
 pub enum LightType {
@@ -45,3 +71,131 @@ pub enum LightType {
     Point = 3,
     Cone = 4,
 }
+
+/// Pixel layout of raw texture data.  
+/// `Luminance` and `LuminanceAlpha` are the WebGL 1 counterparts of single and
+/// two-channel (R8, RG8) formats.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub enum PixelFormat {
+    Rgba = 1,
+    Rgb = 2,
+    LuminanceAlpha = 3,
+    Luminance = 4,
+    Alpha = 5,
+}
+
+impl PixelFormat {
+    /// Number of channels per pixel for this format
+    pub fn get_channel_count(&self) -> usize {
+        match self {
+            PixelFormat::Rgba => 4,
+            PixelFormat::Rgb => 3,
+            PixelFormat::LuminanceAlpha => 2,
+            PixelFormat::Luminance => 1,
+            PixelFormat::Alpha => 1,
+        }
+    }
+
+    /// WebGL format constant for this format
+    pub fn get_gl_format(&self) -> u32 {
+        match self {
+            PixelFormat::Rgba => WebGlRenderingContext::RGBA,
+            PixelFormat::Rgb => WebGlRenderingContext::RGB,
+            PixelFormat::LuminanceAlpha => WebGlRenderingContext::LUMINANCE_ALPHA,
+            PixelFormat::Luminance => WebGlRenderingContext::LUMINANCE,
+            PixelFormat::Alpha => WebGlRenderingContext::ALPHA,
+        }
+    }
+}
+
+/// Dimensions and format of raw texture data.
+#[derive(Clone, Copy)]
+pub struct PixelLayout {
+    /// Width of the texture, in pixels
+    pub width: u32,
+
+    /// Height of the texture, in pixels
+    pub height: u32,
+
+    /// Channels of each pixel
+    pub format: PixelFormat,
+}
+
+impl PixelLayout {
+    /// Constructor
+    pub fn new(width: u32, height: u32, format: PixelFormat) -> PixelLayout {
+        PixelLayout {
+            width,
+            height,
+            format,
+        }
+    }
+
+    /// Number of values needed to fill a texture with this layout.  
+    /// Returns an error if it does not fit in a `usize`.
+    pub fn get_value_count(&self) -> Result<usize, Error> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(self.format.get_channel_count()))
+            .ok_or_else(|| {
+                Error::Load(format!(
+                    "A {}x{} texture is too large to be created.",
+                    self.width, self.height
+                ))
+            })
+    }
+
+    /// Checks that `length` values are exactly enough for a texture with this layout.
+    pub fn check_data_length(&self, length: usize) -> Result<(), Error> {
+        let expected = self.get_value_count()?;
+        if length == expected {
+            Ok(())
+        } else {
+            Err(Error::Load(format!(
+                "Texture data has {} values, but a {}x{} texture in this format needs {}.",
+                length, self.width, self.height, expected
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector3_data_round_trips_through_nalgebra() {
+        let vector = Vector3::new(1.5, -2.0, 3.25);
+        assert_eq!(Vector3::from(Vector3Data::from(vector)), vector);
+        let point = Point3::new(-4.0, 0.5, 8.0);
+        assert_eq!(Point3::from(Vector3Data::from(point)), point);
+    }
+
+    #[test]
+    fn single_channel_layout_accepts_one_byte_per_pixel() {
+        let layout = PixelLayout::new(4, 2, PixelFormat::Luminance);
+        assert_eq!(layout.get_value_count(), Ok(8));
+        assert!(layout.check_data_length(8).is_ok());
+    }
+
+    #[test]
+    fn layout_counts_every_channel() {
+        let layout = PixelLayout::new(2, 2, PixelFormat::Rgba);
+        assert_eq!(layout.get_value_count(), Ok(16));
+        assert!(layout.check_data_length(4).is_err());
+    }
+
+    #[test]
+    fn layout_rejects_extra_data() {
+        let layout = PixelLayout::new(3, 1, PixelFormat::Rgb);
+        assert!(layout.check_data_length(10).is_err());
+    }
+
+    #[test]
+    fn oversized_layouts_are_rejected() {
+        let layout = PixelLayout::new(u32::MAX, u32::MAX, PixelFormat::Rgba);
+        assert!(layout.get_value_count().is_err());
+        assert!(layout.check_data_length(0).is_err());
+    }
+}